@@ -0,0 +1,83 @@
+//! Build script for hassha.
+//!
+//! With the `embed-melodies` feature enabled, every audio file found in
+//! `HASSHA_EMBED_DIR` (default: `assets/melodies/`) is embedded into the binary
+//! via `include_bytes!`, and a missing or empty directory fails the build.
+//! Without the feature, an empty table is generated.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Audio file extensions picked up from the embed directory
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "wav", "ogg", "m4a", "aac", "flac"];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=HASSHA_EMBED_DIR");
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR not set"));
+    let out_file = out_dir.join("embedded_melodies.rs");
+
+    let files = if env::var_os("CARGO_FEATURE_EMBED_MELODIES").is_some() {
+        let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+        let embed_dir = env::var_os("HASSHA_EMBED_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| manifest_dir.join("assets").join("melodies"));
+        let embed_dir = manifest_dir.join(embed_dir);
+
+        println!("cargo:rerun-if-changed={}", embed_dir.display());
+        let files = collect_audio_files(&embed_dir);
+        // A binary built for an air-gapped machine would otherwise only fail
+        // there, when the first melody is downloaded
+        if files.is_empty() {
+            panic!(
+                "embed-melodies: no audio files in {} (set HASSHA_EMBED_DIR)",
+                embed_dir.display()
+            );
+        }
+        files
+    } else {
+        Vec::new()
+    };
+
+    let mut code = String::new();
+    code.push_str("/// Audio files embedded at build time, keyed by file name\n");
+    code.push_str("pub static EMBEDDED: &[(&str, &[u8])] = &[\n");
+    for path in &files {
+        let filename = path.file_name().unwrap().to_string_lossy();
+        code.push_str(&format!(
+            "    ({:?}, include_bytes!({:?})),\n",
+            filename,
+            path.display().to_string()
+        ));
+    }
+    code.push_str("];\n");
+
+    fs::write(&out_file, code).expect("Failed to write embedded_melodies.rs");
+}
+
+/// Collect all audio files in a directory (non-recursive), sorted by name
+fn collect_audio_files(dir: &Path) -> Vec<PathBuf> {
+    let entries = fs::read_dir(dir).unwrap_or_else(|e| {
+        panic!(
+            "embed-melodies: cannot read {} (set HASSHA_EMBED_DIR): {}",
+            dir.display(),
+            e
+        )
+    });
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        })
+        .collect();
+
+    files.sort();
+    files
+}
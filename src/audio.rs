@@ -0,0 +1,224 @@
+//! Audio decoding and rendering for hassha.
+//!
+//! Melody sequences are decoded, joined with silent gaps and written to a
+//! single WAV file, so the whole sequence plays gaplessly in one player
+//! invocation.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+use symphonia::core::codecs::audio::AudioDecoderOptions;
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::probe::Hint;
+use symphonia::core::formats::{FormatOptions, TrackType};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+
+/// Sample rate of rendered audio
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// Channel count of rendered audio
+pub const CHANNELS: usize = 2;
+
+/// Interleaved stereo samples at [`SAMPLE_RATE`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pcm {
+    pub samples: Vec<f32>,
+}
+
+impl Pcm {
+    /// Convert interleaved samples of any rate and channel count
+    pub fn from_interleaved(samples: &[f32], sample_rate: u32, channels: usize) -> Self {
+        if channels == 0 || sample_rate == 0 {
+            return Self::default();
+        }
+
+        // Down- or up-mix to stereo, keeping the first two channels
+        let frames: Vec<[f32; CHANNELS]> = samples
+            .chunks_exact(channels)
+            .map(|frame| [frame[0], frame[if channels > 1 { 1 } else { 0 }]])
+            .collect();
+
+        let frames = resample(&frames, sample_rate);
+        Self {
+            samples: frames.into_iter().flatten().collect(),
+        }
+    }
+
+    /// Silence of the given length
+    pub fn silence(duration: Duration) -> Self {
+        let frames = (duration.as_secs_f64() * SAMPLE_RATE as f64).round() as usize;
+        Self {
+            samples: vec![0.0; frames * CHANNELS],
+        }
+    }
+
+    /// Append another buffer
+    pub fn append(&mut self, other: &Pcm) {
+        self.samples.extend_from_slice(&other.samples);
+    }
+
+    /// Encode as a 16-bit PCM WAV file
+    pub fn to_wav(&self) -> Vec<u8> {
+        let data_len = (self.samples.len() * 2) as u32;
+        let block_align = (CHANNELS * 2) as u16;
+
+        let mut bytes = Vec::with_capacity(44 + data_len as usize);
+        bytes.extend(b"RIFF");
+        bytes.extend((36 + data_len).to_le_bytes());
+        bytes.extend(b"WAVE");
+
+        bytes.extend(b"fmt ");
+        bytes.extend(16u32.to_le_bytes());
+        bytes.extend(1u16.to_le_bytes()); // PCM
+        bytes.extend((CHANNELS as u16).to_le_bytes());
+        bytes.extend(SAMPLE_RATE.to_le_bytes());
+        bytes.extend((SAMPLE_RATE * block_align as u32).to_le_bytes());
+        bytes.extend(block_align.to_le_bytes());
+        bytes.extend(16u16.to_le_bytes());
+
+        bytes.extend(b"data");
+        bytes.extend(data_len.to_le_bytes());
+        for sample in &self.samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            bytes.extend(value.to_le_bytes());
+        }
+
+        bytes
+    }
+}
+
+/// Linearly resample stereo frames to [`SAMPLE_RATE`]
+fn resample(frames: &[[f32; CHANNELS]], sample_rate: u32) -> Vec<[f32; CHANNELS]> {
+    if sample_rate == SAMPLE_RATE || frames.is_empty() {
+        return frames.to_vec();
+    }
+
+    let ratio = sample_rate as f64 / SAMPLE_RATE as f64;
+    let len = (frames.len() as f64 / ratio).round() as usize;
+
+    (0..len)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let index = position as usize;
+            let next = (index + 1).min(frames.len() - 1);
+            let t = (position - index as f64) as f32;
+            let a = frames[index.min(frames.len() - 1)];
+            let b = frames[next];
+            [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t]
+        })
+        .collect()
+}
+
+/// Decode an audio file (MP3 or WAV)
+pub fn decode_file(path: &Path) -> Result<Pcm> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open audio file: {}", path.display()))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let mut format = symphonia::default::get_probe()
+        .probe(
+            &hint,
+            stream,
+            FormatOptions::default(),
+            MetadataOptions::default(),
+        )
+        .with_context(|| format!("Unsupported audio format: {}", path.display()))?;
+
+    let track = format
+        .default_track(TrackType::Audio)
+        .with_context(|| format!("No audio track in {}", path.display()))?;
+    let track_id = track.id;
+    let params = track
+        .codec_params
+        .as_ref()
+        .and_then(|params| params.audio())
+        .with_context(|| format!("No audio track in {}", path.display()))?;
+
+    let mut decoder = symphonia::default::get_codecs()
+        .make_audio_decoder(params, &AudioDecoderOptions::default())
+        .with_context(|| format!("Unsupported audio codec: {}", path.display()))?;
+
+    let mut pcm = Pcm::default();
+    let mut buffer: Vec<f32> = Vec::new();
+
+    while let Some(packet) = format
+        .next_packet()
+        .with_context(|| format!("Failed to read audio: {}", path.display()))?
+    {
+        if packet.track_id != track_id {
+            continue;
+        }
+
+        match decoder.decode(&packet) {
+            Ok(decoded) => {
+                let spec = decoded.spec();
+                let (sample_rate, channels) = (spec.rate(), spec.channels().count());
+
+                buffer.resize(decoded.samples_interleaved(), 0.0);
+                decoded.copy_to_slice_interleaved(&mut buffer);
+                pcm.append(&Pcm::from_interleaved(&buffer, sample_rate, channels));
+            }
+            // Skip corrupt frames
+            Err(DecodeError::DecodeError(_)) => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to decode {}", path.display()));
+            }
+        }
+    }
+
+    Ok(pcm)
+}
+
+/// Render audio files one after another, separated by `gap` of silence
+pub fn render_sequence(paths: &[&Path], gap: Duration) -> Result<Pcm> {
+    let silence = Pcm::silence(gap);
+    let mut output = Pcm::default();
+
+    for (i, path) in paths.iter().enumerate() {
+        if i > 0 {
+            output.append(&silence);
+        }
+        output.append(&decode_file(path)?);
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_interleaved() {
+        let mono = Pcm::from_interleaved(&[0.5, -0.5], SAMPLE_RATE, 1);
+        assert_eq!(mono.samples, vec![0.5, 0.5, -0.5, -0.5]);
+
+        let surround = Pcm::from_interleaved(&[0.1, 0.2, 0.3, 0.4], SAMPLE_RATE, 4);
+        assert_eq!(surround.samples, vec![0.1, 0.2]);
+
+        // 22.05 kHz doubles in length
+        let low = Pcm::from_interleaved(&[0.0, 0.0, 1.0, 1.0], 22050, 2);
+        assert_eq!(low.samples.len(), 8);
+        assert_eq!(low.samples[2], 0.5);
+    }
+
+    #[test]
+    fn test_wav_roundtrip_duration() {
+        let pcm = Pcm::silence(Duration::from_millis(300));
+        assert_eq!(pcm.samples.len(), 13230 * CHANNELS);
+
+        let wav = pcm.to_wav();
+        assert_eq!(
+            crate::probe::bytes_duration(wav.leak()),
+            Some(Duration::from_millis(300))
+        );
+    }
+}
@@ -3,8 +3,25 @@
 //! Downloads and caches audio files in `~/.hassha/audio/`.
 
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::audio;
+use crate::config::{HasshaConfig, MelodySource};
+use crate::melodies::{MelodyInfo, MelodyRegistry};
+use crate::selector::{self, Selection, Selector};
+use crate::synth::{self, SYNTH_PREFIX};
+
+/// Name of the index file inside an exported cache pack
+const PACK_INDEX_FILE: &str = "index.json";
+
+/// Current version of the cache pack index format
+const PACK_INDEX_VERSION: u32 = 1;
 
 /// Get the cache directory path
 pub fn cache_dir() -> Result<PathBuf> {
@@ -58,12 +75,14 @@ pub fn get_cached(url: &str) -> Result<Option<PathBuf>> {
 pub fn download_and_cache(url: &str) -> Result<PathBuf> {
     // Check if already cached
     if let Some(path) = get_cached(url)? {
+        log::debug!(url, path:% = path.display(); "Cache hit");
         return Ok(path);
     }
 
     let cache_path = cache_path_for_url(url)?;
 
     // Download the file
+    log::info!(url; "Downloading");
     let response =
         reqwest::blocking::get(url).with_context(|| format!("Failed to download: {}", url))?;
 
@@ -76,40 +95,339 @@ pub fn download_and_cache(url: &str) -> Result<PathBuf> {
         .with_context(|| format!("Failed to read response body from: {}", url))?;
 
     // Write to cache
-    fs::write(&cache_path, &bytes)
-        .with_context(|| format!("Failed to write cache file: {}", cache_path.display()))?;
+    write_cache_file(&cache_path, &bytes)?;
+    log::debug!(url, bytes = bytes.len(), path:% = cache_path.display(); "Downloaded and cached");
+
+    Ok(cache_path)
+}
+
+/// Write bytes into the cache for a given URL, unless already cached
+pub fn write_to_cache(url: &str, bytes: &[u8]) -> Result<PathBuf> {
+    if let Some(path) = get_cached(url)? {
+        return Ok(path);
+    }
+
+    let cache_path = cache_path_for_url(url)?;
+    write_cache_file(&cache_path, bytes)?;
 
     Ok(cache_path)
 }
 
+/// Write a cache file atomically via a temporary file and rename, so that
+/// concurrent writers (e.g., parallel prefetch) never expose a partial file
+fn write_cache_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp_path = temp_cache_path(path);
+    fs::write(&tmp_path, bytes)
+        .with_context(|| format!("Failed to write cache file: {}", tmp_path.display()))?;
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to write cache file: {}", path.display()))?;
+
+    Ok(())
+}
+
+/// A unique hidden temporary file next to a cache file, to be renamed over it
+fn temp_cache_path(path: &Path) -> PathBuf {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let filename = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(
+        ".{}.{}-{}.part",
+        filename,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
 /// Resolve a melody source to a local file path.
 ///
 /// The source can be:
-/// - A predefined melody ID (e.g., "JY-Shibuya") -> downloads from yamanot.es
+/// - A predefined or catalog melody ID (e.g., "JY-Shibuya") -> served from the
+///   embedded melodies or a catalog's local file if available, otherwise
+///   downloads from its URL
+/// - A selector (e.g., "tag:short") -> resolves a randomly chosen melody
+/// - A synthesized sound (e.g., "synth:door-chime") -> rendered into the cache
 /// - A URL (e.g., "https://...") -> downloads and caches
 /// - A local file path (e.g., "/path/to/file.mp3") -> returns as-is
-pub fn resolve_melody_path(source: &str) -> Result<PathBuf> {
-    use crate::melodies::MelodyRegistry;
+pub fn resolve_melody_path(source: &str, registry: &MelodyRegistry) -> Result<PathBuf> {
+    // Pick a concrete melody for selectors (e.g., "tag:short")
+    if Selector::parse(source).is_some() {
+        let id = selector::select(source, registry, Selection::Random)?;
+        log::debug!(selector = source, melody = id.as_str(); "Selected melody");
+        return resolve_melody_path(&id, registry);
+    }
+
+    if let Some(name) = source.strip_prefix(SYNTH_PREFIX) {
+        return synth_path(name);
+    }
 
-    // Check if it's a predefined melody
-    let registry = MelodyRegistry::new();
+    // Check if it's a known melody
     if let Some(melody) = registry.get(source) {
-        let url = melody.url();
+        if let Some(path) = melody.local_path() {
+            if path.exists() {
+                return Ok(path.to_path_buf());
+            }
+            anyhow::bail!("Audio file for {} not found: {}", melody.id, path.display());
+        }
+
+        let url = melody.location.to_string();
+        if let Some(bytes) = melody.embedded() {
+            log::debug!(melody = melody.id, url = url.as_str(); "Using embedded audio");
+            return write_to_cache(&url, bytes);
+        }
         return download_and_cache(&url);
     }
 
     // Check if it's a URL
-    if source.starts_with("http://") || source.starts_with("https://") {
+    if is_url(source) {
         return download_and_cache(source);
     }
 
     // Treat as local file path
     let path = Path::new(source);
     if path.exists() {
-        Ok(path.to_path_buf())
-    } else {
-        anyhow::bail!("Audio file not found: {}", source)
+        return Ok(path.to_path_buf());
+    }
+
+    // ID-shaped strings are most likely typos of a known melody
+    if registry.looks_like_id(source) {
+        anyhow::bail!(unknown_id_message(source, registry));
     }
+
+    anyhow::bail!("Audio file not found: {}", source)
+}
+
+/// Describe how a melody source would be resolved, without downloading,
+/// rendering or choosing anything. Fails if it can't be resolved.
+pub fn explain_source(
+    source: &str,
+    registry: &MelodyRegistry,
+    selection: Selection,
+) -> Result<String> {
+    if let Some(selector) = Selector::parse(source) {
+        let candidates = selector?.candidates(registry);
+        if candidates.is_empty() {
+            anyhow::bail!("{} matches no melodies", source);
+        }
+        let offline = candidates
+            .iter()
+            .filter(|m| !matches!(cache_status(m), Ok(CacheStatus::NotCached)))
+            .count();
+        let order = match selection {
+            Selection::Random => "one chosen at random",
+            Selection::Sequential => "chosen in order",
+        };
+        return Ok(format!(
+            "{} matches {} melodies, {}; {} available offline",
+            source,
+            candidates.len(),
+            order,
+            offline
+        ));
+    }
+
+    if let Some(name) = source.strip_prefix(SYNTH_PREFIX) {
+        if !synth::SOUNDS.contains(&name) {
+            anyhow::bail!("Unknown synthesized sound: {}", source);
+        }
+        let path = cache_dir()?.join(format!("synth-{}.wav", name));
+        return Ok(if path.exists() {
+            format!("{}: synthesized, cached at {}", source, path.display())
+        } else {
+            format!("{}: synthesized, would be rendered into the cache", source)
+        });
+    }
+
+    if let Some(melody) = registry.get(source) {
+        return Ok(match cache_status(melody)? {
+            CacheStatus::Local(path) if path.exists() => {
+                format!("{}: local file {}", source, path.display())
+            }
+            CacheStatus::Local(path) => {
+                anyhow::bail!("Audio file for {} not found: {}", melody.id, path.display())
+            }
+            CacheStatus::Embedded => format!("{}: embedded in the binary", source),
+            CacheStatus::Cached(path, _) => {
+                format!("{}: cached at {}", source, path.display())
+            }
+            CacheStatus::NotCached => {
+                format!("{}: not cached, would download {}", source, melody.location)
+            }
+        });
+    }
+
+    if is_url(source) {
+        return Ok(match get_cached(source)? {
+            Some(path) => format!("{}: cached at {}", source, path.display()),
+            None => format!("{}: not cached, would download", source),
+        });
+    }
+
+    if Path::new(source).exists() {
+        return Ok(format!("{}: local file", source));
+    }
+
+    if registry.looks_like_id(source) {
+        anyhow::bail!(unknown_id_message(source, registry));
+    }
+
+    anyhow::bail!("Audio file not found: {}", source)
+}
+
+/// Render a synthesized sound into the cache, unless already cached
+fn synth_path(name: &str) -> Result<PathBuf> {
+    // Only known names are used in the file name
+    if !synth::SOUNDS.contains(&name) {
+        anyhow::bail!(
+            "Unknown synthesized sound: {}{} (available: {})",
+            SYNTH_PREFIX,
+            name,
+            synth::SOUNDS.join(", ")
+        );
+    }
+
+    let path = ensure_cache_dir()?.join(format!("synth-{}.wav", name));
+    if path.exists() {
+        return Ok(path);
+    }
+
+    let pcm = synth::render(name).context("Failed to synthesize sound")?;
+    write_cache_file(&path, &pcm.to_wav())?;
+
+    Ok(path)
+}
+
+/// A melody source resolved to a single playable file
+#[derive(Debug)]
+pub struct ResolvedAudio {
+    /// The audio file to play
+    pub path: PathBuf,
+    /// The concrete sources played, with selectors resolved
+    pub sources: Vec<String>,
+}
+
+impl ResolvedAudio {
+    /// The played sources, e.g. "JY-Shinjuku + synth:door-chime"
+    pub fn label(&self) -> String {
+        self.sources.join(" + ")
+    }
+}
+
+/// Resolve a melody source, including sequences, to a single audio file.
+///
+/// Selectors are resolved with `selection`. Sequences are rendered into one
+/// cached WAV file so they play gaplessly in a single player invocation.
+pub fn resolve_audio(
+    source: &MelodySource,
+    registry: &MelodyRegistry,
+    selection: Selection,
+) -> Result<ResolvedAudio> {
+    let sources = source
+        .parts()
+        .into_iter()
+        .map(|part| selector::select(part, registry, selection))
+        .collect::<Result<Vec<_>>>()?;
+
+    let paths = sources
+        .iter()
+        .map(|source| resolve_melody_path(source, registry))
+        .collect::<Result<Vec<_>>>()?;
+
+    let path = match source {
+        MelodySource::Single(_) => paths.into_iter().next().context("Empty melody source")?,
+        MelodySource::Sequence { gap, .. } => sequence_path(&paths, gap.unwrap_or_default())?,
+    };
+    log::debug!(source:% = source, path:% = path.display(); "Resolved audio");
+
+    Ok(ResolvedAudio { path, sources })
+}
+
+/// Render a sequence into the cache, unless already rendered
+fn sequence_path(paths: &[PathBuf], gap: Duration) -> Result<PathBuf> {
+    // Key on the inputs' paths, sizes and modification times, so edited
+    // local files are re-rendered
+    let mut key = format!("gap={}", gap.as_millis());
+    for path in paths {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        key.push_str(&format!(
+            "\n{}:{}:{}",
+            path.display(),
+            metadata.len(),
+            modified
+        ));
+    }
+
+    let hash = sha256_hex(key.as_bytes());
+    let path = ensure_cache_dir()?.join(format!("sequence-{}.wav", &hash[..16]));
+    if path.exists() {
+        return Ok(path);
+    }
+
+    let parts: Vec<&Path> = paths.iter().map(PathBuf::as_path).collect();
+    let pcm = audio::render_sequence(&parts, gap)?;
+    write_cache_file(&path, &pcm.to_wav())?;
+
+    Ok(path)
+}
+
+/// Build an "unknown melody ID" error message with suggestions
+pub fn unknown_id_message(source: &str, registry: &MelodyRegistry) -> String {
+    let suggestions: Vec<&str> = registry.suggest(source).iter().map(|m| m.id).collect();
+
+    match suggestions.as_slice() {
+        [] => format!(
+            "Unknown melody ID: {} (run `hassha list` to see available melodies)",
+            source
+        ),
+        [one] => format!("Unknown melody ID: {} (did you mean {}?)", source, one),
+        many => format!(
+            "Unknown melody ID: {} (did you mean one of: {}?)",
+            source,
+            many.join(", ")
+        ),
+    }
+}
+
+/// Where a melody's audio comes from on this machine
+pub enum CacheStatus {
+    /// A local file from a catalog or pack
+    Local(PathBuf),
+    /// Embedded in the binary
+    Embedded,
+    /// Downloaded into the cache, with its size in bytes
+    Cached(PathBuf, u64),
+    /// Not downloaded yet
+    NotCached,
+}
+
+/// Get the cache status of a melody
+pub fn cache_status(melody: &MelodyInfo) -> Result<CacheStatus> {
+    if let Some(path) = melody.local_path() {
+        return Ok(CacheStatus::Local(path.to_path_buf()));
+    }
+
+    if melody.embedded().is_some() {
+        return Ok(CacheStatus::Embedded);
+    }
+
+    match get_cached(&melody.location.to_string())? {
+        Some(path) => {
+            let size = fs::metadata(&path)?.len();
+            Ok(CacheStatus::Cached(path, size))
+        }
+        None => Ok(CacheStatus::NotCached),
+    }
+}
+
+/// Check whether a melody source is an HTTP(S) URL
+pub fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
 }
 
 /// Get cache statistics
@@ -117,6 +435,7 @@ pub struct CacheStats {
     pub location: PathBuf,
     pub file_count: usize,
     pub total_size: u64,
+    pub embedded_count: usize,
 }
 
 /// Get information about the cache
@@ -128,6 +447,7 @@ pub fn cache_info() -> Result<CacheStats> {
             location: dir,
             file_count: 0,
             total_size: 0,
+            embedded_count: crate::embedded::count(),
         });
     }
 
@@ -146,6 +466,7 @@ pub fn cache_info() -> Result<CacheStats> {
         location: dir,
         file_count,
         total_size,
+        embedded_count: crate::embedded::count(),
     })
 }
 
@@ -169,18 +490,482 @@ pub fn cache_clear() -> Result<usize> {
     Ok(cleared)
 }
 
-/// Download all predefined melodies
-pub fn prefetch_all() -> Result<Vec<(String, Result<PathBuf>)>> {
-    use crate::melodies::MELODIES;
+/// Default number of parallel downloads for prefetch
+pub const DEFAULT_PREFETCH_JOBS: usize = 8;
+
+/// Select the melody sources to prefetch.
+///
+/// - `line`: only registry melodies on this line (e.g., "JY")
+/// - `ids`: only these registry melody IDs
+/// - `configured`: only melodies referenced by the given config
+///
+/// With no filters, all registry melodies are selected. Filters combine as
+/// an intersection.
+pub fn prefetch_targets(
+    registry: &MelodyRegistry,
+    line: Option<&str>,
+    ids: &[String],
+    configured: Option<&HasshaConfig>,
+) -> Result<Vec<String>> {
+    let mut wanted_ids = HashSet::new();
+    for id in ids {
+        match registry.get(id) {
+            Some(melody) => wanted_ids.insert(melody.id),
+            None => anyhow::bail!("Unknown melody ID: {}", id),
+        };
+    }
+
+    let mut targets: Vec<String> = match configured {
+        // Configured sources may be URLs or selectors too; local paths need
+        // no prefetching
+        Some(config) => config
+            .melody_sources()
+            .into_iter()
+            .flat_map(|source| match Selector::parse(&source) {
+                Some(Ok(selector)) => selector
+                    .prefetch_candidates(registry)
+                    .iter()
+                    .map(|m| m.id.to_string())
+                    .collect(),
+                Some(Err(_)) => Vec::new(),
+                None => vec![source],
+            })
+            .filter(|source| registry.get(source).is_some() || is_url(source))
+            .collect(),
+        None => registry.all().iter().map(|m| m.id.to_string()).collect(),
+    };
+
+    if let Some(line) = line {
+        if !registry
+            .all()
+            .iter()
+            .any(|m| m.line.eq_ignore_ascii_case(line))
+        {
+            anyhow::bail!("Unknown line: {}", line);
+        }
+        targets.retain(|source| {
+            registry
+                .get(source)
+                .is_some_and(|m| m.line.eq_ignore_ascii_case(line))
+        });
+    }
+
+    if !wanted_ids.is_empty() {
+        targets.retain(|source| {
+            registry
+                .get(source)
+                .is_some_and(|m| wanted_ids.contains(m.id))
+        });
+    }
+
+    let mut seen = HashSet::new();
+    targets.retain(|source| seen.insert(source.clone()));
+
+    Ok(targets)
+}
+
+/// Download melody sources in parallel with at most `jobs` concurrent downloads.
+///
+/// `on_progress` is called from worker threads as each source completes, with
+/// the number of completed sources so far. Results are returned in input order.
+pub fn prefetch<F>(
+    sources: &[String],
+    registry: &MelodyRegistry,
+    jobs: usize,
+    on_progress: F,
+) -> Vec<(String, Result<PathBuf>)>
+where
+    F: Fn(usize, &str, &Result<PathBuf>) + Sync,
+{
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let next = AtomicUsize::new(0);
+    let completed = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<PathBuf>>>> =
+        Mutex::new(sources.iter().map(|_| None).collect());
 
-    let results: Vec<_> = MELODIES
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, sources.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(source) = sources.get(i) else {
+                        break;
+                    };
+
+                    let result = resolve_melody_path(source, registry);
+                    let done = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                    on_progress(done, source, &result);
+
+                    results.lock().unwrap()[i] = Some(result);
+                }
+            });
+        }
+    });
+
+    sources
         .iter()
-        .map(|melody| {
-            let url = melody.url();
-            let result = download_and_cache(&url);
-            (melody.id.to_string(), result)
-        })
-        .collect();
-
-    Ok(results)
+        .cloned()
+        .zip(results.into_inner().unwrap().into_iter().flatten())
+        .collect()
+}
+
+/// Index stored in an exported cache pack
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheIndex {
+    /// Index format version
+    pub version: u32,
+
+    /// All audio files contained in the pack
+    pub files: Vec<CacheIndexEntry>,
+}
+
+/// A single file in a cache pack index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheIndexEntry {
+    /// File name inside the cache directory
+    pub filename: String,
+
+    /// File size in bytes
+    pub size: u64,
+
+    /// Hex-encoded SHA-256 checksum of the file contents
+    pub sha256: String,
+}
+
+/// Result of importing a cache pack
+pub struct ImportStats {
+    /// Files newly added to the cache
+    pub imported: usize,
+
+    /// Files already present in the cache
+    pub skipped: usize,
+}
+
+/// Compute the hex-encoded SHA-256 checksum of some bytes
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Build an index of the downloaded audio files in a cache directory
+fn build_cache_index(dir: &Path) -> Result<CacheIndex> {
+    let mut files = Vec::new();
+
+    if dir.exists() {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let filename = entry.file_name().to_string_lossy().to_string();
+            if !entry.file_type()?.is_file() || !is_cached_download(&filename) {
+                continue;
+            }
+
+            let bytes = fs::read(entry.path())?;
+            files.push(CacheIndexEntry {
+                filename,
+                size: bytes.len() as u64,
+                sha256: sha256_hex(&bytes),
+            });
+        }
+    }
+
+    files.sort_by(|a, b| a.filename.cmp(&b.filename));
+
+    Ok(CacheIndex {
+        version: PACK_INDEX_VERSION,
+        files,
+    })
+}
+
+/// Whether a cache file is downloaded audio worth sharing, rather than a
+/// temporary file left by an interrupted write or a synthesized sound or
+/// sequence rendered locally
+fn is_cached_download(filename: &str) -> bool {
+    !(filename.starts_with('.')
+        || filename.starts_with("synth-")
+        || filename.starts_with("sequence-"))
+}
+
+/// Export the cache as a zstd-compressed tar pack.
+///
+/// The pack contains `index.json` followed by every file in the index.
+/// Returns the number of exported audio files.
+pub fn cache_export(output: &Path) -> Result<usize> {
+    export_pack(&cache_dir()?, output)
+}
+
+fn export_pack(dir: &Path, output: &Path) -> Result<usize> {
+    let index = build_cache_index(dir)?;
+
+    let file = fs::File::create(output)
+        .with_context(|| format!("Failed to create pack: {}", output.display()))?;
+    let encoder = zstd::Encoder::new(file, 0)?.auto_finish();
+    let mut builder = tar::Builder::new(encoder);
+
+    let index_json = serde_json::to_vec_pretty(&index)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(index_json.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, PACK_INDEX_FILE, index_json.as_slice())?;
+
+    for entry in &index.files {
+        builder
+            .append_path_with_name(dir.join(&entry.filename), &entry.filename)
+            .with_context(|| format!("Failed to add {} to pack", entry.filename))?;
+    }
+
+    builder.into_inner()?;
+
+    Ok(index.files.len())
+}
+
+/// Import a cache pack created by [`cache_export`].
+///
+/// Files are streamed to temporary files and verified against the checksums
+/// in the pack index. Nothing is added to the cache unless every file
+/// verifies. Files already present in the cache are kept as-is.
+pub fn cache_import(input: &Path) -> Result<ImportStats> {
+    import_pack(&ensure_cache_dir()?, input)
+}
+
+fn import_pack(dir: &Path, input: &Path) -> Result<ImportStats> {
+    let mut staged = Vec::new();
+    let result = stage_pack(dir, input, &mut staged);
+
+    // Move verified files into place, or clean up after a failed import
+    match result {
+        Ok(skipped) => {
+            for (tmp_path, path) in &staged {
+                fs::rename(tmp_path, path)
+                    .with_context(|| format!("Failed to write cache file: {}", path.display()))?;
+            }
+            Ok(ImportStats {
+                imported: staged.len(),
+                skipped,
+            })
+        }
+        Err(e) => {
+            for (tmp_path, _) in &staged {
+                let _ = fs::remove_file(tmp_path);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Verify a pack and write its new files next to their cache paths,
+/// recording `(temporary, final)` paths in `staged`. Returns the number of
+/// files already in the cache.
+fn stage_pack(dir: &Path, input: &Path, staged: &mut Vec<(PathBuf, PathBuf)>) -> Result<usize> {
+    let file = fs::File::open(input)
+        .with_context(|| format!("Failed to open pack: {}", input.display()))?;
+    let decoder = zstd::Decoder::new(file)?;
+    let mut archive = tar::Archive::new(decoder);
+    let mut entries = archive.entries()?;
+
+    // The index comes first, so the files can be checked as they stream by
+    let index: CacheIndex = match entries.next() {
+        Some(entry) => {
+            let entry = entry?;
+            if entry.path()?.as_os_str() != PACK_INDEX_FILE {
+                anyhow::bail!("Pack does not start with an {}", PACK_INDEX_FILE);
+            }
+            serde_json::from_reader(entry).context("Failed to parse pack index")?
+        }
+        None => anyhow::bail!("Pack is empty"),
+    };
+    if index.version > PACK_INDEX_VERSION {
+        anyhow::bail!("Unsupported pack index version: {}", index.version);
+    }
+
+    let mut expected: HashMap<&str, &CacheIndexEntry> = HashMap::new();
+    for entry in &index.files {
+        if !is_plain_filename(&entry.filename) {
+            anyhow::bail!("Invalid file name in pack index: {}", entry.filename);
+        }
+        expected.insert(&entry.filename, entry);
+    }
+
+    let mut skipped = 0;
+    for entry in entries {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().to_string();
+        // Files not in the index are ignored
+        let Some(expected) = expected.remove(name.as_str()) else {
+            continue;
+        };
+
+        let path = dir.join(&expected.filename);
+        if path.exists() {
+            skipped += 1;
+            continue;
+        }
+
+        let tmp_path = temp_cache_path(&path);
+        let mut tmp = fs::File::create(&tmp_path)
+            .with_context(|| format!("Failed to write cache file: {}", tmp_path.display()))?;
+        staged.push((tmp_path, path));
+
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+        let mut buffer = [0u8; 64 * 1024];
+        loop {
+            let n = entry.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+            tmp.write_all(&buffer[..n])?;
+            size += n as u64;
+        }
+
+        if size != expected.size || format!("{:x}", hasher.finalize()) != expected.sha256 {
+            anyhow::bail!("Checksum mismatch for {}", expected.filename);
+        }
+    }
+
+    if let Some(filename) = expected.keys().next() {
+        anyhow::bail!("File listed in index is missing: {}", filename);
+    }
+
+    Ok(skipped)
+}
+
+/// Check that a pack file name cannot escape the cache directory
+fn is_plain_filename(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains('/') && !name.contains('\\')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn test_prefetch_targets_by_line() {
+        let registry = MelodyRegistry::new();
+        let targets = prefetch_targets(&registry, Some("nex"), &[], None).unwrap();
+        assert_eq!(targets.len(), 4);
+        assert!(targets.iter().all(|t| t.starts_with("NEX-")));
+
+        assert!(prefetch_targets(&registry, Some("XX"), &[], None).is_err());
+    }
+
+    #[test]
+    fn test_prefetch_targets_by_ids() {
+        let registry = MelodyRegistry::new();
+        let ids = vec!["jy-tokyo".to_string(), "JK-Akihabara".to_string()];
+        let targets = prefetch_targets(&registry, None, &ids, None).unwrap();
+        assert_eq!(targets, vec!["JY-Tokyo", "JK-Akihabara"]);
+
+        let targets = prefetch_targets(&registry, Some("JK"), &ids, None).unwrap();
+        assert_eq!(targets, vec!["JK-Akihabara"]);
+
+        assert!(prefetch_targets(&registry, None, &["JY-Nowhere".to_string()], None).is_err());
+    }
+
+    #[test]
+    fn test_prefetch_targets_configured() {
+        let registry = MelodyRegistry::new();
+        let config: HasshaConfig = toml::from_str(
+            r#"
+[hooks.Stop]
+melody = "JY-Shibuya"
+
+[hooks.Notification]
+melody = "https://example.com/custom.mp3"
+
+[hooks.SessionStart]
+melody = "/path/to/local.mp3"
+"#,
+        )
+        .unwrap();
+
+        let mut targets = prefetch_targets(&registry, None, &[], Some(&config)).unwrap();
+        targets.sort();
+        assert_eq!(
+            targets,
+            vec!["JY-Shibuya", "https://example.com/custom.mp3"]
+        );
+    }
+
+    #[test]
+    fn test_unknown_id_suggestion() {
+        let registry = MelodyRegistry::new();
+        let err = resolve_melody_path("JY-Shibya", &registry).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown melody ID: JY-Shibya (did you mean JY-Shibuya?)"
+        );
+
+        let err = resolve_melody_path("/no/such/file.mp3", &registry).unwrap_err();
+        assert!(err.to_string().starts_with("Audio file not found"));
+    }
+
+    #[test]
+    fn test_is_plain_filename() {
+        assert!(is_plain_filename("sh3.mp3"));
+        assert!(!is_plain_filename("../sh3.mp3"));
+        assert!(!is_plain_filename("audio/sh3.mp3"));
+        assert!(!is_plain_filename(".."));
+        assert!(!is_plain_filename(""));
+    }
+
+    #[test]
+    fn test_pack_roundtrip() {
+        let root = std::env::temp_dir().join(format!("hassha-pack-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let (source, target) = (root.join("source"), root.join("target"));
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        let pack = root.join("pack.tar.zst");
+
+        fs::write(source.join("sh3.mp3"), b"sh3").unwrap();
+        fs::write(source.join("seseragi.mp3"), b"seseragi").unwrap();
+        fs::write(source.join(".sh5.mp3.1-0.part"), b"partial").unwrap();
+        fs::write(source.join("synth-door-chime.wav"), b"chime").unwrap();
+        assert_eq!(export_pack(&source, &pack).unwrap(), 2);
+
+        fs::write(target.join("sh3.mp3"), b"sh3").unwrap();
+        let stats = import_pack(&target, &pack).unwrap();
+        assert_eq!((stats.imported, stats.skipped), (1, 1));
+        assert_eq!(fs::read(target.join("seseragi.mp3")).unwrap(), b"seseragi");
+        assert!(!target.join(".sh5.mp3.1-0.part").exists());
+
+        // A corrupt file fails the import without leaving anything behind
+        fs::write(source.join("seseragi.mp3"), b"corrupt").unwrap();
+        let mut index = build_cache_index(&source).unwrap();
+        index.files[0].sha256 = sha256_hex(b"seseragi");
+        fs::remove_file(target.join("seseragi.mp3")).unwrap();
+        let file = fs::File::create(&pack).unwrap();
+        let mut builder = tar::Builder::new(zstd::Encoder::new(file, 0).unwrap().auto_finish());
+        let index_json = serde_json::to_vec(&index).unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(index_json.len() as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, PACK_INDEX_FILE, index_json.as_slice())
+            .unwrap();
+        builder
+            .append_path_with_name(source.join("seseragi.mp3"), "seseragi.mp3")
+            .unwrap();
+        builder.into_inner().unwrap();
+
+        assert!(import_pack(&target, &pack).is_err());
+        let left: Vec<_> = fs::read_dir(&target).unwrap().collect();
+        assert_eq!(left.len(), 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
 }
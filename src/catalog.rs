@@ -0,0 +1,280 @@
+//! External melody catalogs for hassha.
+//!
+//! Catalogs extend the built-in melody table without a new release. They are
+//! TOML or JSON files loaded from `~/.hassha/catalogs/` and from the `catalogs`
+//! list in project configs:
+//!
+//! ```toml
+//! [[melodies]]
+//! id = "JC-Tokyo"
+//! line = "JC"
+//! line_name = "Chuo"
+//! station = "Tokyo"
+//! station_jp = "東京"
+//! melody_name = "Jupiter"
+//! url = "https://example.com/jupiter.mp3"   # or: file = "audio/jupiter.mp3"
+//! duration = "7.5s"                         # optional metadata
+//! credit = "Gustav Holst"
+//! tags = ["calm"]
+//! ```
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cache::is_url;
+use crate::config::deserialize_opt_duration;
+use crate::melodies::{AudioLocation, MelodyInfo};
+
+/// A catalog file
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct Catalog {
+    /// Melodies defined by this catalog
+    #[serde(default)]
+    pub melodies: Vec<CatalogMelody>,
+}
+
+/// A melody entry in a catalog file
+#[derive(Debug, Clone, Deserialize)]
+pub struct CatalogMelody {
+    /// The melody ID (e.g., "JC-Tokyo")
+    pub id: String,
+    /// The line code (e.g., "JC")
+    pub line: String,
+    /// The line name in English
+    pub line_name: String,
+    /// The station name in English
+    pub station: String,
+    /// The station name in Japanese
+    #[serde(default)]
+    pub station_jp: String,
+    /// The melody name
+    #[serde(default)]
+    pub melody_name: String,
+    /// A file name on yamanot.es
+    #[serde(default)]
+    pub filename: Option<String>,
+    /// A full URL to the audio file
+    #[serde(default)]
+    pub url: Option<String>,
+    /// A local audio file, relative to the catalog file
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    /// Hex-encoded SHA-256 checksum of the local audio file (required in packs)
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Audio duration (e.g., "6.5s")
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub duration: Option<Duration>,
+    /// Original composer / arrangement credit
+    #[serde(default)]
+    pub credit: Option<String>,
+    /// Descriptive tags (e.g., "calm", "upbeat")
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl CatalogMelody {
+    /// Convert into a `MelodyInfo`, resolving `file` relative to `base_dir`.
+    /// With a namespace, the ID becomes `{namespace}:{id}`.
+    ///
+    /// Catalogs are loaded once per process, so the strings are leaked to get
+    /// the `'static` lifetime shared with the built-in table.
+    pub fn into_info(self, base_dir: &Path, namespace: Option<&str>) -> Result<MelodyInfo> {
+        let location = match (self.filename, self.url, self.file) {
+            (Some(filename), None, None) => AudioLocation::Yamanotes(leak(filename)),
+            (None, Some(url), None) => {
+                if !is_url(&url) {
+                    anyhow::bail!("Melody {} has an invalid URL: {}", self.id, url);
+                }
+                AudioLocation::Url(leak(url))
+            }
+            (None, None, Some(file)) => {
+                AudioLocation::File(Box::leak(base_dir.join(file).into_boxed_path()))
+            }
+            _ => anyhow::bail!(
+                "Melody {} must set exactly one of `filename`, `url` or `file`",
+                self.id
+            ),
+        };
+
+        if !self
+            .id
+            .strip_prefix(&self.line)
+            .is_some_and(|rest| rest.starts_with('-') && rest.len() > 1)
+        {
+            anyhow::bail!(
+                "Melody ID {} does not follow the {}-<Station> scheme",
+                self.id,
+                self.line
+            );
+        }
+
+        let id = match namespace {
+            Some(namespace) => format!("{}:{}", namespace, self.id),
+            None => self.id,
+        };
+
+        Ok(MelodyInfo {
+            id: leak(id),
+            line: leak(self.line),
+            line_name: leak(self.line_name),
+            station: leak(self.station),
+            station_jp: leak(self.station_jp),
+            melody_name: leak(self.melody_name),
+            location,
+            duration_ms: self.duration.map(|d| d.as_millis() as u32),
+            credit: self.credit.map(leak),
+            tags: Box::leak(self.tags.into_iter().map(leak).collect()),
+        })
+    }
+}
+
+fn leak(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+/// Get the user catalog directory (`~/.hassha/catalogs/`)
+pub fn user_catalog_dir() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    Ok(home.join(".hassha").join("catalogs"))
+}
+
+/// List catalog files (`.toml` and `.json`) in a directory, sorted by name
+pub fn catalog_files_in(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && is_catalog_file(&path) {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+fn is_catalog_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("toml") | Some("json")
+    )
+}
+
+/// Parse catalog content based on the file extension
+pub fn parse_catalog(content: &str, path: &Path) -> Result<Catalog> {
+    let catalog = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(content)?,
+        _ => toml::from_str(content)?,
+    };
+    Ok(catalog)
+}
+
+/// Load a catalog file
+pub fn load_catalog(path: &Path) -> Result<Catalog> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read catalog: {}", path.display()))?;
+
+    parse_catalog(&content, path)
+        .with_context(|| format!("Failed to parse catalog: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_toml_catalog() {
+        let content = r#"
+[[melodies]]
+id = "JC-Tokyo"
+line = "JC"
+line_name = "Chuo"
+station = "Tokyo"
+station_jp = "東京"
+melody_name = "Jupiter"
+url = "https://example.com/jupiter.mp3"
+duration = "7.5s"
+credit = "Gustav Holst"
+tags = ["calm"]
+
+[[melodies]]
+id = "IN-Office"
+line = "IN"
+line_name = "In-house"
+station = "Office"
+file = "audio/office.mp3"
+"#;
+
+        let catalog = parse_catalog(content, Path::new("chuo.toml")).unwrap();
+        assert_eq!(catalog.melodies.len(), 2);
+
+        let mut melodies = catalog.melodies.into_iter();
+        let jc = melodies
+            .next()
+            .unwrap()
+            .into_info(Path::new("/c"), None)
+            .unwrap();
+        assert_eq!(jc.url().unwrap(), "https://example.com/jupiter.mp3");
+        assert_eq!(jc.duration(), Some(Duration::from_millis(7500)));
+        assert_eq!(jc.credit, Some("Gustav Holst"));
+        assert!(jc.has_tag("calm"));
+        assert!(!jc.has_tag("short"));
+
+        let office = melodies
+            .next()
+            .unwrap()
+            .into_info(Path::new("/c"), None)
+            .unwrap();
+        assert_eq!(office.local_path(), Some(Path::new("/c/audio/office.mp3")));
+        assert_eq!(office.url(), None);
+    }
+
+    #[test]
+    fn test_parse_json_catalog() {
+        let content = r#"{"melodies": [{
+            "id": "JO-Osaka", "line": "JO", "line_name": "Osaka Loop",
+            "station": "Osaka", "filename": "osaka.mp3"
+        }]}"#;
+
+        let catalog = parse_catalog(content, Path::new("osaka.json")).unwrap();
+        let info = catalog.melodies[0]
+            .clone()
+            .into_info(Path::new("/"), None)
+            .unwrap();
+        assert_eq!(info.url().unwrap(), "https://yamanot.es/audio/osaka.mp3");
+    }
+
+    #[test]
+    fn test_invalid_catalog_entries() {
+        let bad_id = CatalogMelody {
+            id: "Osaka".to_string(),
+            line: "JO".to_string(),
+            line_name: "Osaka Loop".to_string(),
+            station: "Osaka".to_string(),
+            station_jp: String::new(),
+            melody_name: String::new(),
+            filename: Some("osaka.mp3".to_string()),
+            url: None,
+            file: None,
+            sha256: None,
+            duration: None,
+            credit: None,
+            tags: Vec::new(),
+        };
+        assert!(bad_id.clone().into_info(Path::new("/"), None).is_err());
+
+        let no_source = CatalogMelody {
+            id: "JO-Osaka".to_string(),
+            filename: None,
+            ..bad_id
+        };
+        assert!(no_source.into_info(Path::new("/"), None).is_err());
+    }
+}
@@ -1,6 +1,12 @@
 //! CLI argument parsing for hassha.
 
 use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::event::PayloadOptions;
+use crate::history::{HistoryFormat, Outcome};
+use crate::listing::ListFormat;
 
 #[derive(Parser)]
 #[command(name = "hassha")]
@@ -9,6 +15,14 @@ use clap::{Args, Parser, Subcommand};
 )]
 #[command(version)]
 pub struct Cli {
+    /// Show log messages (-v for info, -vv for debug, -vvv for trace)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Don't show log messages, not even warnings
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -19,25 +33,191 @@ pub enum Commands {
     Hook {
         /// The hook event name (e.g., Stop, Notification, SessionStart)
         event: String,
+
+        /// Explain step by step what would happen, without playing anything
+        /// or writing history
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Print errors and exit 1 instead of logging them to
+        /// ~/.hassha/hassha.log and exiting 0
+        #[arg(long)]
+        strict: bool,
+    },
+
+    /// Explain step by step how a hook event would be handled
+    Explain {
+        /// The hook event name (e.g., Stop, PostToolUse)
+        event: String,
+
+        /// Project directory the event happens in
+        #[arg(long, default_value = ".")]
+        cwd: PathBuf,
+
+        #[command(flatten)]
+        payload: PayloadOptions,
+    },
+
+    /// Simulate a hook event with a realistic Claude Code payload
+    Simulate {
+        /// The hook event name (e.g., Stop, PostToolUse)
+        event: String,
+
+        /// Project directory the event happens in
+        #[arg(long, default_value = ".")]
+        cwd: PathBuf,
+
+        #[command(flatten)]
+        payload: PayloadOptions,
+
+        /// Don't play audio (the null audio backend)
+        #[arg(long)]
+        null_audio: bool,
+
+        /// Don't write history or update the playback and turn state, so
+        /// the simulation doesn't affect cooldowns or real hooks. By default
+        /// a simulated event is recorded like a real one.
+        #[arg(long)]
+        no_record: bool,
+
+        /// Print the payload JSON (e.g., as a test fixture) instead of running it
+        #[arg(long)]
+        print: bool,
     },
 
     /// Play a melody directly
     Play {
-        /// Melody ID (e.g., JY-Shibuya), URL, or file path
-        melody: String,
+        /// Melody ID (e.g., JY-Shibuya), preset (e.g., @success), selector
+        /// (e.g., tag:short), synthesized sound (e.g., synth:door-chime), URL,
+        /// or file path. Several melodies are played as one sequence.
+        #[arg(required = true)]
+        melody: Vec<String>,
 
-        /// Volume level (0.0 - 1.0)
-        #[arg(short, long, default_value = "1.0")]
-        volume: f32,
+        /// Volume level (0.0 - 1.0), defaults to the preset's volume or 1.0
+        #[arg(long)]
+        volume: Option<f32>,
+
+        /// Silence between the melodies of a sequence (e.g., 300ms)
+        #[arg(long, value_parser = crate::config::parse_duration)]
+        gap: Option<Duration>,
     },
 
-    /// List available predefined melodies
-    List,
+    /// List available melodies, optionally filtered
+    List {
+        /// Only melodies on this line (e.g., JY, JK, NEX)
+        #[arg(long)]
+        line: Option<String>,
+
+        /// Filter by English or Japanese station name (substring)
+        #[arg(long)]
+        station: Option<String>,
+
+        /// Filter by melody name (substring)
+        #[arg(long)]
+        melody: Option<String>,
+
+        /// Search all fields (substring)
+        #[arg(long)]
+        search: Option<String>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t)]
+        format: ListFormat,
+    },
+
+    /// Show everything known about a melody
+    Info {
+        /// Melody ID (e.g., JY-Shibuya)
+        melody: String,
+    },
+
+    /// Check the current config for unknown melodies and invalid selectors
+    Check,
 
-    /// Show recent melody history
+    /// Show recent melody history, optionally filtered
     History {
         #[command(subcommand)]
         command: Option<HistoryCommands>,
+
+        /// Only this hook event (e.g., Stop)
+        #[arg(long)]
+        event: Option<String>,
+
+        /// Only this project directory and its subdirectories (e.g., .)
+        #[arg(long)]
+        project: Option<PathBuf>,
+
+        /// Only entries newer than a duration (e.g., 1h, 7d) or an RFC 3339 timestamp
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only melodies matching a pattern (e.g., JY-*)
+        #[arg(long)]
+        melody: Option<String>,
+
+        /// Only entries with this outcome (e.g., error, matcher-miss)
+        #[arg(long, value_enum)]
+        outcome: Option<Outcome>,
+
+        /// Only this agent session (ID or prefix)
+        #[arg(long)]
+        session: Option<String>,
+
+        /// Keep watching and print new entries as hooks fire (Ctrl-C to stop)
+        #[arg(short, long, conflicts_with = "format")]
+        follow: bool,
+
+        /// Maximum number of entries to show (0 for all)
+        #[arg(short = 'n', long, default_value_t = crate::history::DEFAULT_DISPLAY_ENTRIES)]
+        limit: usize,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t)]
+        format: HistoryFormat,
+    },
+
+    /// Re-play the melodies of a session at accelerated speed
+    Replay {
+        /// Session ID or prefix (see `hassha history sessions`)
+        session: String,
+
+        /// Speed-up of the pauses between melodies
+        #[arg(long, default_value_t = crate::replay::DEFAULT_SPEED)]
+        speed: f64,
+
+        /// Volume level (0.0 - 1.0)
+        #[arg(long, default_value_t = 1.0)]
+        volume: f32,
+    },
+
+    /// Summarize melody history: events per day, top melodies, projects, tools
+    Stats {
+        /// Only entries newer than a duration (e.g., 7d) or an RFC 3339 timestamp
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only this project directory and its subdirectories (e.g., .)
+        #[arg(long)]
+        project: Option<PathBuf>,
+
+        /// Number of melodies, projects and tools to show
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show recent errors from hooks (~/.hassha/hassha.log)
+    Log {
+        /// Maximum number of lines to show (0 for all)
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+
+        /// Show log messages of every level, not just errors
+        #[arg(long)]
+        all: bool,
     },
 
     /// Cache management
@@ -46,6 +226,12 @@ pub enum Commands {
         command: CacheCommands,
     },
 
+    /// Melody pack management
+    Pack {
+        #[command(subcommand)]
+        command: PackCommands,
+    },
+
     /// Install hassha plugin for Claude Code or OpenCode
     Install {
         #[command(flatten)]
@@ -79,12 +265,86 @@ pub enum CacheCommands {
     /// Clear all cached audio files
     Clear,
 
-    /// Download all predefined melodies
-    Prefetch,
+    /// Download predefined melodies in parallel
+    Prefetch {
+        /// Only melodies on this line (e.g., JY, JK, NEX)
+        #[arg(long)]
+        line: Option<String>,
+
+        /// Only melodies referenced by the config for the current directory
+        #[arg(long)]
+        configured: bool,
+
+        /// Only these melody IDs (comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        ids: Vec<String>,
+
+        /// Maximum number of parallel downloads
+        #[arg(short, long, default_value_t = crate::cache::DEFAULT_PREFETCH_JOBS)]
+        jobs: usize,
+    },
+
+    /// Export cached audio as a portable pack (e.g., pack.tar.zst)
+    Export {
+        /// Output pack file
+        output: PathBuf,
+    },
+
+    /// Import a pack created by `cache export`, verifying checksums
+    Import {
+        /// Input pack file
+        input: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PackCommands {
+    /// Install a pack from a directory, .tar.zst archive, or URL
+    Add {
+        /// Pack directory, archive path, or URL
+        source: String,
+    },
+
+    /// List installed packs
+    List,
+
+    /// Remove an installed pack
+    Remove {
+        /// Pack name
+        name: String,
+    },
 }
 
 #[derive(Subcommand)]
 pub enum HistoryCommands {
     /// Clear melody history
     Clear,
+
+    /// Apply the retention settings from the user config now
+    Compact,
+
+    /// List agent sessions with start and end times and event counts
+    Sessions,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+
+        // -v/--quiet work after the subcommand, next to its own flags
+        let cli =
+            Cli::try_parse_from(["hassha", "play", "JY-Tokyo", "-vv", "--volume", "0.5"]).unwrap();
+        assert_eq!(cli.verbose, 2);
+        assert!(
+            Cli::try_parse_from(["hassha", "list", "--quiet"])
+                .unwrap()
+                .quiet
+        );
+        assert!(Cli::try_parse_from(["hassha", "list", "-v", "-q"]).is_err());
+    }
 }
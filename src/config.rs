@@ -4,9 +4,19 @@
 //! The config resolver walks up the directory tree to find the nearest config file.
 
 use anyhow::{Context, Result};
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cache::{is_url, unknown_id_message};
+use crate::event::HookEvent;
+use crate::logging::ErrorPolicy;
+use crate::melodies::MelodyRegistry;
+use crate::playback::Overlap;
+use crate::selector::{Selection, Selector};
+use crate::synth::{SOUNDS, SYNTH_PREFIX};
+use crate::template;
 
 /// Main configuration structure for hassha
 #[derive(Debug, Clone, Deserialize, Default)]
@@ -14,6 +24,136 @@ pub struct HasshaConfig {
     /// Hook-specific configurations
     #[serde(default)]
     pub hooks: HashMap<String, HookConfig>,
+
+    /// Melody catalog files, relative to the config file's directory
+    #[serde(default)]
+    pub catalogs: Vec<PathBuf>,
+
+    /// Named melody presets, referenced from hooks as `melody = "@name"`
+    #[serde(default)]
+    pub melodies: HashMap<String, MelodyPreset>,
+
+    /// Default selection strategy for selectors such as `JY-*`
+    #[serde(default)]
+    pub selection: Selection,
+
+    /// What to do when a hook fires while another melody is playing
+    #[serde(default)]
+    pub overlap: Overlap,
+
+    /// Minimum time between two melodies of the same hook event (e.g., "10s")
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub cooldown: Option<Duration>,
+
+    /// History retention. Only read from the user config.
+    #[serde(default)]
+    pub history: HistoryConfig,
+
+    /// What `hassha hook` does when handling an event fails. Only read from
+    /// the user config.
+    #[serde(default)]
+    pub on_error: Option<ErrorPolicy>,
+}
+
+/// History retention settings
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct HistoryConfig {
+    /// Maximum number of entries to keep (default: 1000)
+    #[serde(default)]
+    pub max_entries: Option<usize>,
+
+    /// Maximum age of entries to keep (e.g., "30d")
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub max_age: Option<Duration>,
+}
+
+/// A melody source: a single melody, or a sequence played as one unit
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum MelodySource {
+    /// A melody ID, preset, selector, URL, or local file path
+    Single(String),
+
+    /// Melodies played back to back, e.g.
+    /// `{ sequence = ["JY-Shinjuku", "synth:door-chime"], gap = "300ms" }`
+    Sequence {
+        sequence: Vec<String>,
+        #[serde(default, deserialize_with = "deserialize_opt_duration")]
+        gap: Option<Duration>,
+    },
+}
+
+impl MelodySource {
+    /// The individual sources, in playback order
+    pub fn parts(&self) -> Vec<&str> {
+        match self {
+            MelodySource::Single(source) => vec![source.as_str()],
+            MelodySource::Sequence { sequence, .. } => {
+                sequence.iter().map(String::as_str).collect()
+            }
+        }
+    }
+
+    /// The preset name if this is a `@name` reference
+    pub fn preset(&self) -> Option<&str> {
+        match self {
+            MelodySource::Single(source) => source.strip_prefix('@'),
+            MelodySource::Sequence { .. } => None,
+        }
+    }
+}
+
+impl From<&str> for MelodySource {
+    fn from(source: &str) -> Self {
+        MelodySource::Single(source.to_string())
+    }
+}
+
+impl PartialEq<&str> for MelodySource {
+    fn eq(&self, other: &&str) -> bool {
+        matches!(self, MelodySource::Single(source) if source == other)
+    }
+}
+
+impl std::fmt::Display for MelodySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.parts().join(" + "))
+    }
+}
+
+/// A named melody preset
+#[derive(Debug, Clone, Deserialize)]
+pub struct MelodyPreset {
+    /// The melody source (ID, selector, URL, local file path, or sequence)
+    pub source: MelodySource,
+
+    /// Optional volume level (0.0 - 1.0)
+    #[serde(default)]
+    pub volume: Option<f32>,
+
+    /// Optional playback limit (e.g., "3s", "500ms")
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub end: Option<Duration>,
+
+    /// Optional selection strategy when the source is a selector
+    #[serde(default)]
+    pub selection: Option<Selection>,
+}
+
+/// A hook melody with presets applied
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedMelody {
+    /// The melody source (ID, selector, URL, local file path, or sequence)
+    pub source: MelodySource,
+
+    /// Volume level (0.0 - 1.0)
+    pub volume: f32,
+
+    /// Optional playback limit
+    pub end: Option<Duration>,
+
+    /// Selection strategy when the source is a selector
+    pub selection: Selection,
 }
 
 /// Configuration for a specific hook event
@@ -23,24 +163,388 @@ pub struct HookConfig {
     /// - A predefined melody ID (e.g., "JY-Shibuya")
     /// - A URL (e.g., "https://example.com/sound.mp3")
     /// - A local file path (e.g., "/path/to/sound.mp3")
-    pub melody: String,
+    /// - A preset name (e.g., "@success")
+    /// - A selector (e.g., "JY-*", "JK-*|JB-*", "tag:short")
+    /// - A synthesized sound (e.g., "synth:door-chime")
+    /// - A sequence (e.g., `{ sequence = ["JY-Shinjuku", "synth:door-chime"] }`)
+    pub melody: MelodySource,
 
-    /// Optional volume level (0.0 - 1.0). Default is 1.0.
-    #[serde(default = "default_volume")]
-    pub volume: f32,
+    /// Optional volume level (0.0 - 1.0). Defaults to the preset's volume, or 1.0.
+    #[serde(default)]
+    pub volume: Option<f32>,
+
+    /// Optional selection strategy. Defaults to the preset's, then the config's.
+    #[serde(default)]
+    pub selection: Option<Selection>,
 
     /// Optional matcher pattern for filtering (e.g., for PostToolUse)
     #[serde(default)]
     pub matcher: Option<String>,
+
+    /// Optional overlap policy. Defaults to the config's.
+    #[serde(default)]
+    pub overlap: Option<Overlap>,
+
+    /// Optional cooldown. Defaults to the config's.
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub cooldown: Option<Duration>,
+
+    /// Optional message shown in the agent's transcript when the melody
+    /// plays, e.g. "🚃 {station} departure". See [`crate::template`].
+    #[serde(default)]
+    pub message: Option<String>,
+
+    /// Optional minimum turn length: only play once the agent has worked
+    /// this long since the session's last prompt (e.g., "60s")
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub min_turn_duration: Option<Duration>,
+
+    /// Other melodies for longer turns (`[[hooks.Stop.turns]]`)
+    #[serde(default)]
+    pub turns: Vec<TurnBucket>,
+}
+
+/// A melody for turns of at least a given length, replacing the rule's
+#[derive(Debug, Clone, Deserialize)]
+pub struct TurnBucket {
+    /// Turns at least this long use this bucket (e.g., "5m")
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub min_turn_duration: Duration,
+
+    /// The melody to play instead of the rule's
+    pub melody: MelodySource,
+
+    /// Optional volume level (0.0 - 1.0). Defaults to the rule's.
+    #[serde(default)]
+    pub volume: Option<f32>,
+
+    /// Optional message template. Defaults to the rule's.
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+impl HasshaConfig {
+    /// All melody sources referenced by this config, with presets expanded
+    pub fn melody_sources(&self) -> Vec<String> {
+        let mut sources: Vec<String> = self
+            .hooks
+            .values()
+            .map(|h| &h.melody)
+            .chain(self.melodies.values().map(|p| &p.source))
+            .flat_map(|source| source.parts())
+            .filter(|source| !source.starts_with('@'))
+            .map(String::from)
+            .collect();
+        sources.sort();
+        sources.dedup();
+        sources
+    }
+
+    /// Resolve a melody reference, expanding `@name` presets.
+    ///
+    /// An explicit `volume` overrides the preset's volume.
+    pub fn resolve_melody(
+        &self,
+        melody: &MelodySource,
+        volume: Option<f32>,
+    ) -> Result<ResolvedMelody> {
+        check_sequence(melody)?;
+
+        let Some(name) = melody.preset() else {
+            return Ok(ResolvedMelody {
+                source: melody.clone(),
+                volume: volume.unwrap_or(DEFAULT_VOLUME),
+                end: None,
+                selection: self.selection,
+            });
+        };
+
+        let preset = self
+            .melodies
+            .get(name)
+            .with_context(|| format!("Unknown melody preset: @{}", name))?;
+
+        if preset.source.preset().is_some() {
+            anyhow::bail!(
+                "Melody preset @{} cannot refer to another preset ({})",
+                name,
+                preset.source
+            );
+        }
+        check_sequence(&preset.source)?;
+        log::debug!(preset = name, source:% = preset.source; "Expanded melody preset");
+
+        Ok(ResolvedMelody {
+            source: preset.source.clone(),
+            volume: volume.or(preset.volume).unwrap_or(DEFAULT_VOLUME),
+            end: preset.end,
+            selection: preset.selection.unwrap_or(self.selection),
+        })
+    }
+
+    /// Check hook and preset melodies against the registry.
+    ///
+    /// Returns a list of problems, e.g. unknown melody IDs, invalid
+    /// selectors or selectors that match no melody.
+    pub fn validate(&self, registry: &MelodyRegistry) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        let mut hooks: Vec<_> = self.hooks.iter().collect();
+        hooks.sort_by_key(|(name, _)| name.as_str());
+        for (name, hook) in hooks {
+            let context = format!("[hooks.{}]", name);
+            check_event(&context, name, hook, &mut problems);
+            if let Some(name) = hook.melody.preset()
+                && !self.melodies.contains_key(name)
+            {
+                problems.push(format!("{}: unknown melody preset @{}", context, name));
+            }
+            check_volume(&context, hook.volume, &mut problems);
+            check_melody(&context, &hook.melody, registry, &mut problems);
+            check_message(&context, hook.message.as_deref(), &mut problems);
+
+            for (i, bucket) in hook.turns.iter().enumerate() {
+                let context = format!("{} turns[{}]", context, i);
+                if let Some(name) = bucket.melody.preset()
+                    && !self.melodies.contains_key(name)
+                {
+                    problems.push(format!("{}: unknown melody preset @{}", context, name));
+                }
+                check_volume(&context, bucket.volume, &mut problems);
+                check_melody(&context, &bucket.melody, registry, &mut problems);
+                check_message(&context, bucket.message.as_deref(), &mut problems);
+            }
+        }
+
+        let mut presets: Vec<_> = self.melodies.iter().collect();
+        presets.sort_by_key(|(name, _)| name.as_str());
+        for (name, preset) in presets {
+            let context = format!("[melodies.{}]", name);
+            if preset.source.preset().is_some() {
+                problems.push(format!(
+                    "{}: presets cannot refer to other presets ({})",
+                    context, preset.source
+                ));
+            }
+            check_volume(&context, preset.volume, &mut problems);
+            check_melody(&context, &preset.source, registry, &mut problems);
+        }
+
+        problems
+    }
+
+    /// Add presets from another config (e.g., the user config) without
+    /// overriding presets defined here
+    pub fn inherit_presets(&mut self, other: &HasshaConfig) {
+        for (name, preset) in &other.melodies {
+            self.melodies
+                .entry(name.clone())
+                .or_insert_with(|| preset.clone());
+        }
+    }
+}
+
+impl HookConfig {
+    /// Resolve this hook's melody against the config's presets
+    pub fn resolve(&self, config: &HasshaConfig) -> Result<ResolvedMelody> {
+        let mut melody = config.resolve_melody(&self.melody, self.volume)?;
+        if let Some(selection) = self.selection {
+            melody.selection = selection;
+        }
+        Ok(melody)
+    }
+
+    /// The overlap policy for this hook
+    pub fn overlap(&self, config: &HasshaConfig) -> Overlap {
+        self.overlap.unwrap_or(config.overlap)
+    }
+
+    /// The cooldown for this hook
+    pub fn cooldown(&self, config: &HasshaConfig) -> Option<Duration> {
+        self.cooldown.or(config.cooldown)
+    }
+
+    /// The bucket of `turns` for a turn of this length: the one with the
+    /// longest `min_turn_duration` the turn reached
+    pub fn turn_bucket(&self, turn: Duration) -> Option<&TurnBucket> {
+        self.turns
+            .iter()
+            .filter(|bucket| bucket.min_turn_duration <= turn)
+            .max_by_key(|bucket| bucket.min_turn_duration)
+    }
+
+    /// This rule with a bucket's melody, volume and message
+    pub fn with_bucket(&self, bucket: &TurnBucket) -> HookConfig {
+        HookConfig {
+            melody: bucket.melody.clone(),
+            volume: bucket.volume.or(self.volume),
+            message: bucket.message.clone().or_else(|| self.message.clone()),
+            ..self.clone()
+        }
+    }
+}
+
+/// Sequences cannot be empty or contain presets
+fn check_sequence(melody: &MelodySource) -> Result<()> {
+    if let MelodySource::Sequence { sequence, .. } = melody {
+        if sequence.is_empty() {
+            anyhow::bail!("Melody sequence is empty");
+        }
+        if let Some(part) = sequence.iter().find(|part| part.starts_with('@')) {
+            anyhow::bail!("Melody sequences cannot contain presets ({})", part);
+        }
+    }
+    Ok(())
+}
+
+/// Check a message template for unknown variables
+fn check_message(context: &str, message: Option<&str>, problems: &mut Vec<String>) {
+    for name in message.map(template::unknown_variables).unwrap_or_default() {
+        problems.push(format!(
+            "{}: unknown variable {{{}}} in message (available: {})",
+            context,
+            name,
+            template::VARIABLES.join(", ")
+        ));
+    }
+}
+
+fn check_melody(
+    context: &str,
+    melody: &MelodySource,
+    registry: &MelodyRegistry,
+    problems: &mut Vec<String>,
+) {
+    if let Err(e) = check_sequence(melody) {
+        problems.push(format!("{}: {}", context, e));
+    }
+    for part in melody.parts() {
+        check_source(context, part, registry, problems);
+    }
+}
+
+fn check_volume(context: &str, volume: Option<f32>, problems: &mut Vec<String>) {
+    if let Some(volume) = volume
+        && !(0.0..=1.0).contains(&volume)
+    {
+        problems.push(format!(
+            "{}: volume {} is out of range (0.0 - 1.0)",
+            context, volume
+        ));
+    }
+}
+
+fn check_source(
+    context: &str,
+    source: &str,
+    registry: &MelodyRegistry,
+    problems: &mut Vec<String>,
+) {
+    if source.starts_with('@') || is_url(source) {
+        return;
+    }
+
+    if let Some(name) = source.strip_prefix(SYNTH_PREFIX) {
+        if !SOUNDS.contains(&name) {
+            problems.push(format!(
+                "{}: unknown synthesized sound {} (available: {})",
+                context,
+                source,
+                SOUNDS.join(", ")
+            ));
+        }
+        return;
+    }
+
+    if let Some(selector) = Selector::parse(source) {
+        match selector {
+            // Duration filters are ignored: durations are unknown until downloaded
+            Ok(selector) if selector.prefetch_candidates(registry).is_empty() => {
+                problems.push(format!("{}: {} matches no melodies", context, source));
+            }
+            Ok(_) => {}
+            Err(e) => problems.push(format!("{}: {:#}", context, e)),
+        }
+        return;
+    }
+
+    if registry.get(source).is_none()
+        && !Path::new(source).exists()
+        && registry.looks_like_id(source)
+    {
+        problems.push(format!(
+            "{}: {}",
+            context,
+            unknown_id_message(source, registry)
+        ));
+    }
+}
+
+/// Default volume level
+pub const DEFAULT_VOLUME: f32 = 1.0;
+
+/// Parse a human-friendly duration such as "300ms", "3s", "1.5s", "2m", "1h" or "7d"
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let value: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid duration: {:?}", s))?;
+
+    let seconds = match unit.trim() {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" | "min" => value * 60.0,
+        "h" => value * 3600.0,
+        "d" => value * 86400.0,
+        _ => anyhow::bail!("Invalid duration unit in {:?} (use ms, s, m, h or d)", s),
+    };
+
+    Duration::try_from_secs_f64(seconds).with_context(|| format!("Duration out of range: {:?}", s))
 }
 
-fn default_volume() -> f32 {
-    1.0
+/// Deserialize a duration string with [`parse_duration`]
+pub fn deserialize_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse_duration(&value).map_err(serde::de::Error::custom)
+}
+
+/// Deserialize an optional duration string with [`parse_duration`]
+pub fn deserialize_opt_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    value
+        .map(|s| parse_duration(&s).map_err(serde::de::Error::custom))
+        .transpose()
 }
 
 /// The config file name
 pub const CONFIG_FILE: &str = ".hassha/config.toml";
 
+/// Get the user config path (`~/.hassha/config.toml`)
+pub fn user_config_path() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    Ok(home.join(CONFIG_FILE))
+}
+
+/// Load the user config, if present
+pub fn load_user_config() -> Result<Option<HasshaConfig>> {
+    let path = user_config_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    read_config_file(&path).map(Some)
+}
+
 /// Find the config file by walking up the directory tree
 pub fn find_config_file(start_dir: &Path) -> Option<PathBuf> {
     let mut current = start_dir.to_path_buf();
@@ -60,20 +564,84 @@ pub fn find_config_file(start_dir: &Path) -> Option<PathBuf> {
     None
 }
 
-/// Load configuration from a directory (walks up tree to find config)
+/// Load configuration from a directory (walks up tree to find config).
+///
+/// Melody presets from the user config are available in every project.
 pub fn load_config(start_dir: &Path) -> Result<Option<HasshaConfig>> {
     let config_path = match find_config_file(start_dir) {
         Some(path) => path,
-        None => return Ok(None),
+        None => {
+            log::debug!(dir:% = start_dir.display(); "No config file found");
+            return Ok(None);
+        }
     };
 
-    let content = std::fs::read_to_string(&config_path)
+    log::debug!(path:% = config_path.display(); "Loading config file");
+    let mut config = read_config_file(&config_path)?;
+
+    let user_path = user_config_path()?;
+    if user_path != config_path
+        && let Some(user_config) = load_user_config()?
+    {
+        log::debug!(
+            path:% = user_path.display(),
+            presets = user_config.melodies.len();
+            "Inheriting presets from the user config"
+        );
+        config.inherit_presets(&user_config);
+    }
+
+    Ok(Some(config))
+}
+
+/// Read and parse a single config file
+fn read_config_file(config_path: &Path) -> Result<HasshaConfig> {
+    let content = std::fs::read_to_string(config_path)
         .with_context(|| format!("Failed to read config file: {}", config_path.display()))?;
 
-    let config: HasshaConfig = toml::from_str(&content)
+    let mut config: HasshaConfig = toml::from_str(&content)
         .with_context(|| format!("Failed to parse config file: {}", config_path.display()))?;
 
-    Ok(Some(config))
+    let mut names: Vec<&String> = config.hooks.keys().collect();
+    names.sort();
+    for problem in names.into_iter().filter_map(|name| unknown_event(name)) {
+        log::warn!("{}: {}", config_path.display(), problem);
+    }
+
+    if let Some(config_dir) = config_path.parent() {
+        config.catalogs = config
+            .catalogs
+            .iter()
+            .map(|path| config_dir.join(path))
+            .collect();
+    }
+
+    Ok(config)
+}
+
+/// Describe a `[hooks.<name>]` key that isn't a known hook event
+fn unknown_event(name: &str) -> Option<String> {
+    if HookEvent::parse(name).is_known() {
+        return None;
+    }
+
+    Some(match HookEvent::suggest(name) {
+        Some(known) => format!("unknown hook event {:?}, did you mean {}?", name, known),
+        None => format!("unknown hook event {:?}", name),
+    })
+}
+
+/// Check a `[hooks.<name>]` key against the known hook events
+fn check_event(context: &str, name: &str, hook: &HookConfig, problems: &mut Vec<String>) {
+    let event = HookEvent::parse(name);
+    if let Some(problem) = unknown_event(name) {
+        problems.push(format!("{}: {}", context, problem));
+    } else if hook.matcher.as_deref().is_some_and(|m| m != "*") && !event.supports_matcher() {
+        problems.push(format!(
+            "{}: {} events don't support matchers, so the matcher never matches",
+            context, event
+        ));
+    }
 }
 
 /// Get the hook configuration for a specific event
@@ -104,14 +672,14 @@ matcher = "Bash"
 
         assert!(config.hooks.contains_key("Stop"));
         assert_eq!(config.hooks["Stop"].melody, "JY-Shibuya");
-        assert_eq!(config.hooks["Stop"].volume, 0.8);
+        assert_eq!(config.hooks["Stop"].volume, Some(0.8));
 
         assert!(config.hooks.contains_key("Notification"));
         assert_eq!(
             config.hooks["Notification"].melody,
             "https://example.com/custom.mp3"
         );
-        assert_eq!(config.hooks["Notification"].volume, 1.0); // default
+        assert_eq!(config.hooks["Notification"].volume, None); // default
 
         assert!(config.hooks.contains_key("PostToolUse"));
         assert_eq!(
@@ -119,4 +687,271 @@ matcher = "Bash"
             Some("Bash".to_string())
         );
     }
+
+    #[test]
+    fn test_melody_presets() {
+        let toml_str = r#"
+[melodies]
+success = { source = "JY-Ebisu", volume = 0.6, end = "3s" }
+
+[hooks.Stop]
+melody = "@success"
+
+[hooks.Notification]
+melody = "@success"
+volume = 0.9
+
+[hooks.SessionStart]
+melody = "@missing"
+"#;
+
+        let config: HasshaConfig = toml::from_str(toml_str).unwrap();
+
+        let stop = config.hooks["Stop"].resolve(&config).unwrap();
+        assert_eq!(stop.source, "JY-Ebisu");
+        assert_eq!(stop.volume, 0.6);
+        assert_eq!(stop.end, Some(Duration::from_secs(3)));
+
+        let notification = config.hooks["Notification"].resolve(&config).unwrap();
+        assert_eq!(notification.volume, 0.9);
+
+        assert!(config.hooks["SessionStart"].resolve(&config).is_err());
+        assert_eq!(config.melody_sources(), vec!["JY-Ebisu"]);
+    }
+
+    #[test]
+    fn test_melody_sequence() {
+        let config: HasshaConfig = toml::from_str(
+            r#"
+cooldown = "30s"
+
+[hooks.Stop]
+melody = { sequence = ["JY-Shinjuku", "synth:door-chime"], gap = "300ms" }
+overlap = "skip"
+
+[hooks.Notification]
+melody = { sequence = ["JY-Tokyo", "@chime"] }
+cooldown = "5s"
+"#,
+        )
+        .unwrap();
+
+        let stop = &config.hooks["Stop"];
+        assert_eq!(
+            stop.melody,
+            MelodySource::Sequence {
+                sequence: vec!["JY-Shinjuku".to_string(), "synth:door-chime".to_string()],
+                gap: Some(Duration::from_millis(300)),
+            }
+        );
+        assert_eq!(stop.melody.to_string(), "JY-Shinjuku + synth:door-chime");
+        assert_eq!(stop.overlap(&config), Overlap::Skip);
+        assert_eq!(stop.cooldown(&config), Some(Duration::from_secs(30)));
+        assert!(stop.resolve(&config).is_ok());
+
+        let notification = &config.hooks["Notification"];
+        assert_eq!(notification.overlap(&config), Overlap::Allow);
+        assert_eq!(notification.cooldown(&config), Some(Duration::from_secs(5)));
+        assert!(notification.resolve(&config).is_err());
+
+        assert_eq!(
+            config.melody_sources(),
+            vec!["JY-Shinjuku", "JY-Tokyo", "synth:door-chime"]
+        );
+
+        let problems = config.validate(&MelodyRegistry::new());
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("cannot contain presets"));
+    }
+
+    #[test]
+    fn test_selection_strategy() {
+        let config: HasshaConfig = toml::from_str(
+            r#"
+selection = "sequential"
+
+[melodies]
+shuffle = { source = "JY-*", selection = "random" }
+
+[hooks.Stop]
+melody = "JY-*"
+
+[hooks.Notification]
+melody = "@shuffle"
+
+[hooks.SessionEnd]
+melody = "@shuffle"
+selection = "sequential"
+"#,
+        )
+        .unwrap();
+
+        let resolve = |name: &str| config.hooks[name].resolve(&config).unwrap().selection;
+        assert_eq!(resolve("Stop"), Selection::Sequential);
+        assert_eq!(resolve("Notification"), Selection::Random);
+        assert_eq!(resolve("SessionEnd"), Selection::Sequential);
+    }
+
+    #[test]
+    fn test_validate() {
+        let registry = MelodyRegistry::new();
+        let config: HasshaConfig = toml::from_str(
+            r#"
+[melodies]
+lines = { source = "JK-*|JB-*" }
+typo = { source = "JY-Shibuyaa" }
+
+[hooks.Stop]
+melody = "XX-*"
+
+[hooks.Notification]
+melody = "random:colour=red"
+
+[hooks.SessionStart]
+melody = "@missing"
+volume = 1.5
+
+[hooks.SessionEnd]
+melody = "@lines"
+
+[hooks.PreCompact]
+melody = "https://example.com/sound.mp3"
+
+[hooks.PostToolUse]
+melody = "synth:door-chime"
+"#,
+        )
+        .unwrap();
+
+        let problems = config.validate(&registry);
+        assert_eq!(problems.len(), 5, "{:#?}", problems);
+        assert!(problems[0].starts_with("[hooks.Notification]: Invalid selector"));
+        assert_eq!(
+            problems[1],
+            "[hooks.SessionStart]: unknown melody preset @missing"
+        );
+        assert!(problems[2].contains("volume 1.5 is out of range"));
+        assert_eq!(problems[3], "[hooks.Stop]: XX-* matches no melodies");
+        assert!(problems[4].starts_with("[melodies.typo]: Unknown melody ID: JY-Shibuyaa"));
+    }
+
+    #[test]
+    fn test_validate_events() {
+        let registry = MelodyRegistry::new();
+        let config: HasshaConfig = toml::from_str(
+            r#"
+[hooks.stop]
+melody = "synth:door-chime"
+
+[hooks.Finished]
+melody = "synth:door-chime"
+
+[hooks.UserPromptSubmit]
+melody = "synth:door-chime"
+matcher = "Bash"
+
+[hooks.PostToolUse]
+melody = "synth:door-chime"
+matcher = "Bash"
+message = "{tool} done at {staton}"
+"#,
+        )
+        .unwrap();
+
+        let problems = config.validate(&registry);
+        assert_eq!(
+            problems,
+            vec![
+                r#"[hooks.Finished]: unknown hook event "Finished""#,
+                format!(
+                    "[hooks.PostToolUse]: unknown variable {{staton}} in message (available: {})",
+                    template::VARIABLES.join(", ")
+                )
+                .as_str(),
+                "[hooks.UserPromptSubmit]: UserPromptSubmit events don't support matchers, so the matcher never matches",
+                r#"[hooks.stop]: unknown hook event "stop", did you mean Stop?"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_turn_buckets() {
+        let config: HasshaConfig = toml::from_str(
+            r#"
+[hooks.Stop]
+melody = "synth:door-chime"
+volume = 0.5
+message = "Done after {turn}"
+min_turn_duration = "60s"
+
+[[hooks.Stop.turns]]
+min_turn_duration = "10m"
+melody = "NEX-Shinjuku"
+message = "Long one: {turn}"
+
+[[hooks.Stop.turns]]
+min_turn_duration = "3m"
+melody = "JY-Tokyo"
+"#,
+        )
+        .unwrap();
+
+        let hook = &config.hooks["Stop"];
+        assert_eq!(hook.min_turn_duration, Some(Duration::from_secs(60)));
+        assert!(hook.turn_bucket(Duration::from_secs(120)).is_none());
+
+        let bucket = hook.turn_bucket(Duration::from_secs(300)).unwrap();
+        let rule = hook.with_bucket(bucket);
+        assert_eq!(rule.melody, MelodySource::Single("JY-Tokyo".to_string()));
+        assert_eq!(rule.volume, Some(0.5));
+        assert_eq!(rule.message.as_deref(), Some("Done after {turn}"));
+
+        let bucket = hook.turn_bucket(Duration::from_secs(3600)).unwrap();
+        let rule = hook.with_bucket(bucket);
+        assert_eq!(
+            rule.melody,
+            MelodySource::Single("NEX-Shinjuku".to_string())
+        );
+        assert_eq!(rule.message.as_deref(), Some("Long one: {turn}"));
+    }
+
+    #[test]
+    fn test_inherit_presets() {
+        let mut project: HasshaConfig = toml::from_str(
+            r#"
+[melodies]
+success = { source = "JY-Ebisu" }
+"#,
+        )
+        .unwrap();
+        let user: HasshaConfig = toml::from_str(
+            r#"
+[melodies]
+success = { source = "JY-Tokyo" }
+failure = { source = "JK-Akihabara" }
+"#,
+        )
+        .unwrap();
+
+        project.inherit_presets(&user);
+        assert_eq!(project.melodies["success"].source, "JY-Ebisu");
+        assert_eq!(project.melodies["failure"].source, "JK-Akihabara");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("300ms").unwrap(), Duration::from_millis(300));
+        assert_eq!(parse_duration("3s").unwrap(), Duration::from_secs(3));
+        assert_eq!(parse_duration("1.5s").unwrap(), Duration::from_millis(1500));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+        assert_eq!(
+            parse_duration("7d").unwrap(),
+            Duration::from_secs(7 * 86400)
+        );
+        assert!(parse_duration("3 parsecs").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("1e400").is_err());
+        assert!(parse_duration("99999999999999999999d").is_err());
+    }
 }
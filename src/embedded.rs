@@ -0,0 +1,37 @@
+//! Melodies embedded into the binary at build time.
+//!
+//! Enabled by the `embed-melodies` cargo feature. Embedded files are served
+//! from `resolve_melody_path` without touching the network, which makes the
+//! binary usable on air-gapped machines.
+
+include!(concat!(env!("OUT_DIR"), "/embedded_melodies.rs"));
+
+/// Look up an embedded audio file by its file name (e.g., "sh3.mp3")
+pub fn get(filename: &str) -> Option<&'static [u8]> {
+    EMBEDDED
+        .iter()
+        .find(|(name, _)| *name == filename)
+        .map(|(_, bytes)| *bytes)
+}
+
+/// Number of embedded audio files
+pub fn count() -> usize {
+    EMBEDDED.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_file_not_embedded() {
+        assert!(get("not-a-melody.mp3").is_none());
+    }
+
+    #[test]
+    fn test_embedded_files_are_not_empty() {
+        for (name, bytes) in EMBEDDED {
+            assert!(!bytes.is_empty(), "embedded file is empty: {}", name);
+        }
+    }
+}
@@ -0,0 +1,609 @@
+//! Claude Code hook events and their payloads.
+//!
+//! Each event carries its own fields on stdin; they are deserialized into a
+//! typed [`EventPayload`] according to the event name. Events hassha doesn't
+//! know are kept as [`HookEvent::Unknown`] with their raw payload, so newer
+//! Claude Code events still work with a warning.
+
+use anyhow::{Context, Result};
+use clap::Args;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A Claude Code hook event
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HookEvent {
+    SessionStart,
+    UserPromptSubmit,
+    PreToolUse,
+    PermissionRequest,
+    PostToolUse,
+    PostToolUseFailure,
+    Notification,
+    SubagentStart,
+    SubagentStop,
+    Stop,
+    PreCompact,
+    SessionEnd,
+    /// An event hassha doesn't know, by name
+    Unknown(String),
+}
+
+impl HookEvent {
+    /// All known events, in the order of a session
+    pub const ALL: &[HookEvent] = &[
+        HookEvent::SessionStart,
+        HookEvent::UserPromptSubmit,
+        HookEvent::PreToolUse,
+        HookEvent::PermissionRequest,
+        HookEvent::PostToolUse,
+        HookEvent::PostToolUseFailure,
+        HookEvent::Notification,
+        HookEvent::SubagentStart,
+        HookEvent::SubagentStop,
+        HookEvent::Stop,
+        HookEvent::PreCompact,
+        HookEvent::SessionEnd,
+    ];
+
+    /// Parse an event name (case-sensitive, as in Claude Code)
+    pub fn parse(name: &str) -> Self {
+        Self::ALL
+            .iter()
+            .find(|event| event.name() == name)
+            .cloned()
+            .unwrap_or_else(|| HookEvent::Unknown(name.to_string()))
+    }
+
+    /// The event name as used by Claude Code and in `[hooks.<name>]`
+    pub fn name(&self) -> &str {
+        match self {
+            HookEvent::SessionStart => "SessionStart",
+            HookEvent::UserPromptSubmit => "UserPromptSubmit",
+            HookEvent::PreToolUse => "PreToolUse",
+            HookEvent::PermissionRequest => "PermissionRequest",
+            HookEvent::PostToolUse => "PostToolUse",
+            HookEvent::PostToolUseFailure => "PostToolUseFailure",
+            HookEvent::Notification => "Notification",
+            HookEvent::SubagentStart => "SubagentStart",
+            HookEvent::SubagentStop => "SubagentStop",
+            HookEvent::Stop => "Stop",
+            HookEvent::PreCompact => "PreCompact",
+            HookEvent::SessionEnd => "SessionEnd",
+            HookEvent::Unknown(name) => name,
+        }
+    }
+
+    /// Check whether this is an event hassha knows
+    pub fn is_known(&self) -> bool {
+        !matches!(self, HookEvent::Unknown(_))
+    }
+
+    /// Check whether rules for this event can use a `matcher`
+    pub fn supports_matcher(&self) -> bool {
+        !matches!(self, HookEvent::UserPromptSubmit | HookEvent::Stop)
+    }
+
+    /// A known event whose name differs only in case, for suggestions
+    pub fn suggest(name: &str) -> Option<&'static HookEvent> {
+        Self::ALL
+            .iter()
+            .find(|event| event.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for HookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Fields of tool events (PreToolUse, PostToolUse, PostToolUseFailure,
+/// PermissionRequest)
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct ToolPayload {
+    #[serde(default)]
+    pub tool_name: Option<String>,
+    #[serde(default)]
+    pub tool_input: Option<serde_json::Value>,
+    /// PostToolUse only
+    #[serde(default)]
+    pub tool_response: Option<serde_json::Value>,
+    /// PostToolUseFailure only
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Fields of Notification events
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct NotificationPayload {
+    #[serde(default)]
+    pub message: Option<String>,
+    /// e.g., permission_prompt, idle_prompt
+    #[serde(default)]
+    pub notification_type: Option<String>,
+}
+
+/// Fields of UserPromptSubmit events
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct PromptPayload {
+    #[serde(default)]
+    pub prompt: Option<String>,
+}
+
+/// Fields of Stop, SubagentStart and SubagentStop events
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct StopPayload {
+    /// Whether a stop hook already made the agent continue
+    #[serde(default)]
+    pub stop_hook_active: bool,
+    /// Subagent events only
+    #[serde(default)]
+    pub agent_type: Option<String>,
+}
+
+/// Fields of PreCompact events
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct CompactPayload {
+    /// manual or auto
+    #[serde(default)]
+    pub trigger: Option<String>,
+    #[serde(default)]
+    pub custom_instructions: Option<String>,
+}
+
+/// Fields of SessionStart and SessionEnd events
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct SessionPayload {
+    /// SessionStart: startup, resume, clear, compact
+    #[serde(default)]
+    pub source: Option<String>,
+    /// SessionEnd: clear, logout, prompt_input_exit, other
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Event-specific fields of a hook input
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventPayload {
+    Tool(ToolPayload),
+    Notification(NotificationPayload),
+    Prompt(PromptPayload),
+    Stop(StopPayload),
+    Compact(CompactPayload),
+    Session(SessionPayload),
+    /// The raw input of an unknown event, or of a known event whose fields
+    /// have unexpected types
+    Unknown(serde_json::Value),
+}
+
+impl EventPayload {
+    /// Deserialize the fields of `event` from a hook input. Fields that
+    /// don't match the expected types (e.g., from a newer Claude Code) fall
+    /// back to the untyped payload with a warning, so the hook still runs.
+    pub fn parse(event: &HookEvent, input: serde_json::Value) -> Self {
+        fn fields<T: DeserializeOwned>(
+            input: &serde_json::Value,
+            wrap: fn(T) -> EventPayload,
+        ) -> serde_json::Result<EventPayload> {
+            T::deserialize(input).map(wrap)
+        }
+
+        let payload = match event {
+            HookEvent::PreToolUse
+            | HookEvent::PostToolUse
+            | HookEvent::PostToolUseFailure
+            | HookEvent::PermissionRequest => fields(&input, EventPayload::Tool),
+            HookEvent::Notification => fields(&input, EventPayload::Notification),
+            HookEvent::UserPromptSubmit => fields(&input, EventPayload::Prompt),
+            HookEvent::Stop | HookEvent::SubagentStart | HookEvent::SubagentStop => {
+                fields(&input, EventPayload::Stop)
+            }
+            HookEvent::PreCompact => fields(&input, EventPayload::Compact),
+            HookEvent::SessionStart | HookEvent::SessionEnd => {
+                fields(&input, EventPayload::Session)
+            }
+            HookEvent::Unknown(_) => return EventPayload::Unknown(input),
+        };
+
+        payload.unwrap_or_else(|e| {
+            log::warn!("Invalid {} hook input, matchers won't match: {}", event, e);
+            EventPayload::Unknown(input)
+        })
+    }
+
+    /// The value a rule's `matcher` is checked against, if the event has one
+    pub fn matcher_value(&self) -> Option<&str> {
+        match self {
+            // Tool events match on tool_name
+            EventPayload::Tool(tool) => tool.tool_name.as_deref(),
+            // Notification matches on notification_type
+            EventPayload::Notification(notification) => notification.notification_type.as_deref(),
+            // Subagent events match on agent_type
+            EventPayload::Stop(stop) => stop.agent_type.as_deref(),
+            // PreCompact matches on trigger (manual, auto)
+            EventPayload::Compact(compact) => compact.trigger.as_deref(),
+            // SessionStart matches on source, SessionEnd on reason
+            EventPayload::Session(session) => {
+                session.source.as_deref().or(session.reason.as_deref())
+            }
+            // UserPromptSubmit doesn't support matchers
+            EventPayload::Prompt(_) | EventPayload::Unknown(_) => None,
+        }
+    }
+
+    /// The tool name, for tool events
+    pub fn tool_name(&self) -> Option<&str> {
+        match self {
+            EventPayload::Tool(tool) => tool.tool_name.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Input received from Claude Code hooks via stdin
+#[derive(Debug, Clone, PartialEq)]
+pub struct HookInput {
+    /// The current working directory
+    pub cwd: PathBuf,
+
+    /// Session ID
+    pub session_id: Option<String>,
+
+    /// Path to the session transcript
+    pub transcript_path: Option<PathBuf>,
+
+    /// Event-specific fields
+    pub payload: EventPayload,
+}
+
+/// Event fields for building a hook input. `hassha simulate` fills in
+/// unset fields with typical values; `hassha explain` leaves them out.
+#[derive(Debug, Clone, Default, Args)]
+pub struct PayloadOptions {
+    /// Tool name, for tool events (simulate default: Bash)
+    #[arg(long)]
+    pub tool: Option<String>,
+
+    /// SessionStart source: startup, resume, clear, compact
+    #[arg(long)]
+    pub source: Option<String>,
+
+    /// Notification type: permission_prompt, idle_prompt, ...
+    #[arg(long)]
+    pub notification_type: Option<String>,
+
+    /// Agent type, for subagent events (simulate default: Explore)
+    #[arg(long)]
+    pub agent_type: Option<String>,
+
+    /// PreCompact trigger: manual, auto
+    #[arg(long)]
+    pub trigger: Option<String>,
+
+    /// SessionEnd reason: clear, logout, prompt_input_exit, other
+    #[arg(long)]
+    pub reason: Option<String>,
+
+    /// Session ID
+    #[arg(long, default_value = SIMULATED_SESSION)]
+    pub session_id: String,
+}
+
+/// Session ID of built hook inputs, unless given
+pub const SIMULATED_SESSION: &str = "hassha-simulate";
+
+/// Build a hook input payload following the Claude Code hook schema. With
+/// `defaults`, unset fields get typical values (e.g., the Bash tool);
+/// otherwise only the given fields are set.
+pub fn build_payload(
+    event: &HookEvent,
+    cwd: &Path,
+    options: &PayloadOptions,
+    defaults: bool,
+) -> serde_json::Value {
+    let transcript = dirs::home_dir()
+        .unwrap_or_default()
+        .join(".claude")
+        .join("projects")
+        .join(format!("{}.jsonl", options.session_id));
+    let mut payload = serde_json::json!({
+        "session_id": options.session_id,
+        "transcript_path": transcript,
+        "cwd": cwd,
+        "permission_mode": "default",
+        "hook_event_name": event.name(),
+    });
+
+    let option = |value: &Option<String>, default: &'static str| {
+        value
+            .as_deref()
+            .or_else(|| defaults.then_some(default))
+            .map(str::to_string)
+    };
+
+    let tool = option(&options.tool, "Bash");
+    let tool_input = tool.as_deref().map(|tool| match tool {
+        "Bash" => serde_json::json!({"command": "npm test", "description": "Run tests"}),
+        "Edit" => serde_json::json!({
+            "file_path": cwd.join("README.md"),
+            "old_string": "foo",
+            "new_string": "bar",
+        }),
+        "Write" | "Read" => serde_json::json!({"file_path": cwd.join("README.md")}),
+        _ => serde_json::json!({}),
+    });
+    let agent_type = option(&options.agent_type, "Explore");
+
+    let fields = match event {
+        HookEvent::PreToolUse | HookEvent::PermissionRequest => serde_json::json!({
+            "tool_name": tool,
+            "tool_input": tool_input,
+        }),
+        HookEvent::PostToolUse => serde_json::json!({
+            "tool_name": tool,
+            "tool_input": tool_input,
+            "tool_response": {"success": true},
+        }),
+        HookEvent::PostToolUseFailure => serde_json::json!({
+            "tool_name": tool,
+            "tool_input": tool_input,
+            "error": "Command exited with non-zero status code 1",
+        }),
+        HookEvent::Notification => {
+            let notification_type = option(&options.notification_type, "idle_prompt");
+            let message = match (notification_type.as_deref(), &tool) {
+                (Some("permission_prompt"), Some(tool)) => {
+                    format!("Claude needs your permission to use {}", tool)
+                }
+                _ => "Claude is waiting for your input".to_string(),
+            };
+            serde_json::json!({
+                "message": message,
+                "notification_type": notification_type,
+            })
+        }
+        HookEvent::UserPromptSubmit => serde_json::json!({"prompt": "Run the tests"}),
+        HookEvent::Stop => serde_json::json!({"stop_hook_active": false}),
+        HookEvent::SubagentStart => serde_json::json!({
+            "agent_id": "agent-1",
+            "agent_type": agent_type,
+        }),
+        HookEvent::SubagentStop => serde_json::json!({
+            "stop_hook_active": false,
+            "agent_id": "agent-1",
+            "agent_type": agent_type,
+        }),
+        HookEvent::PreCompact => serde_json::json!({
+            "trigger": option(&options.trigger, "manual"),
+            "custom_instructions": "",
+        }),
+        HookEvent::SessionStart => serde_json::json!({
+            "source": option(&options.source, "startup"),
+        }),
+        HookEvent::SessionEnd => serde_json::json!({
+            "reason": option(&options.reason, "other"),
+        }),
+        HookEvent::Unknown(_) => serde_json::json!({}),
+    };
+
+    if let (Some(payload), serde_json::Value::Object(mut fields)) =
+        (payload.as_object_mut(), fields)
+    {
+        // Unset fields are left out rather than null
+        fields.retain(|_, value| !value.is_null());
+        payload.extend(fields);
+    }
+    payload
+}
+
+/// Fields common to every hook input
+#[derive(Deserialize)]
+struct CommonFields {
+    cwd: PathBuf,
+    #[serde(default)]
+    session_id: Option<String>,
+    #[serde(default)]
+    transcript_path: Option<PathBuf>,
+}
+
+impl HookInput {
+    /// Build the input Claude Code would send for `event` in `cwd`, e.g.
+    /// for `hassha explain` and `hassha simulate`. See [`build_payload`].
+    pub fn build(
+        event: &HookEvent,
+        cwd: &Path,
+        options: &PayloadOptions,
+        defaults: bool,
+    ) -> Result<Self> {
+        Self::parse(
+            event,
+            &build_payload(event, cwd, options, defaults).to_string(),
+        )
+    }
+
+    /// Parse the JSON input of a hook for `event`
+    pub fn parse(event: &HookEvent, json: &str) -> Result<Self> {
+        let value: serde_json::Value =
+            serde_json::from_str(json).context("Failed to parse hook input JSON")?;
+        let common: CommonFields =
+            serde_json::from_value(value.clone()).context("Failed to parse hook input JSON")?;
+
+        Ok(Self {
+            cwd: common.cwd,
+            session_id: common.session_id,
+            transcript_path: common.transcript_path,
+            payload: EventPayload::parse(event, value),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_event() {
+        for event in HookEvent::ALL {
+            assert_eq!(&HookEvent::parse(event.name()), event);
+        }
+
+        let unknown = HookEvent::parse("stop");
+        assert_eq!(unknown, HookEvent::Unknown("stop".to_string()));
+        assert!(!unknown.is_known());
+        assert_eq!(HookEvent::suggest("stop"), Some(&HookEvent::Stop));
+        assert_eq!(HookEvent::suggest("Stopp"), None);
+    }
+
+    #[test]
+    fn test_parse_stop_input() {
+        let json = r#"{
+            "cwd": "/home/user/project",
+            "hook_event_name": "Stop",
+            "session_id": "abc123",
+            "transcript_path": "/home/user/.claude/projects/abc123.jsonl",
+            "stop_hook_active": true
+        }"#;
+
+        let input = HookInput::parse(&HookEvent::Stop, json).unwrap();
+        assert_eq!(input.cwd, PathBuf::from("/home/user/project"));
+        assert_eq!(input.session_id.as_deref(), Some("abc123"));
+        assert!(input.transcript_path.is_some());
+        assert_eq!(
+            input.payload,
+            EventPayload::Stop(StopPayload {
+                stop_hook_active: true,
+                agent_type: None,
+            })
+        );
+        assert_eq!(input.payload.matcher_value(), None);
+    }
+
+    #[test]
+    fn test_parse_tool_input() {
+        let json = r#"{
+            "cwd": "/home/user/project",
+            "hook_event_name": "PostToolUse",
+            "tool_name": "Bash",
+            "tool_input": {"command": "npm test"},
+            "tool_response": {"stdout": "ok"}
+        }"#;
+
+        let input = HookInput::parse(&HookEvent::PostToolUse, json).unwrap();
+        let EventPayload::Tool(tool) = &input.payload else {
+            panic!("expected a tool payload");
+        };
+        assert_eq!(tool.tool_input.as_ref().unwrap()["command"], "npm test");
+        assert!(tool.tool_response.is_some());
+        assert_eq!(input.payload.matcher_value(), Some("Bash"));
+    }
+
+    #[test]
+    fn test_parse_matcher_values() {
+        let parse = |event: HookEvent, json: &str| {
+            HookInput::parse(&event, json)
+                .unwrap()
+                .payload
+                .matcher_value()
+                .map(str::to_string)
+        };
+
+        assert_eq!(
+            parse(
+                HookEvent::SessionStart,
+                r#"{"cwd": "/", "source": "startup"}"#
+            ),
+            Some("startup".to_string())
+        );
+        assert_eq!(
+            parse(
+                HookEvent::Notification,
+                r#"{"cwd": "/", "message": "Waiting", "notification_type": "idle_prompt"}"#
+            ),
+            Some("idle_prompt".to_string())
+        );
+        assert_eq!(
+            parse(
+                HookEvent::SubagentStop,
+                r#"{"cwd": "/", "agent_type": "Explore"}"#
+            ),
+            Some("Explore".to_string())
+        );
+        assert_eq!(
+            parse(
+                HookEvent::UserPromptSubmit,
+                r#"{"cwd": "/", "prompt": "hello"}"#
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_build_payload() {
+        let cwd = Path::new("/home/user/project");
+        let options = PayloadOptions {
+            tool: Some("Edit".to_string()),
+            notification_type: Some("permission_prompt".to_string()),
+            session_id: SIMULATED_SESSION.to_string(),
+            ..Default::default()
+        };
+
+        // Every built payload parses as its event
+        for event in HookEvent::ALL {
+            let input = HookInput::build(event, cwd, &options, true).unwrap();
+            assert_eq!(input.cwd, cwd);
+            assert_eq!(input.session_id.as_deref(), Some(SIMULATED_SESSION));
+        }
+
+        let payload = build_payload(&HookEvent::PostToolUse, cwd, &options, true);
+        assert_eq!(payload["hook_event_name"], "PostToolUse");
+        assert_eq!(payload["tool_name"], "Edit");
+        assert_eq!(
+            payload["tool_input"]["file_path"],
+            "/home/user/project/README.md"
+        );
+
+        let matcher_value = |event: HookEvent| {
+            HookInput::build(&event, cwd, &options, true)
+                .unwrap()
+                .payload
+                .matcher_value()
+                .map(str::to_string)
+        };
+        assert_eq!(matcher_value(HookEvent::PreToolUse).unwrap(), "Edit");
+        assert_eq!(
+            matcher_value(HookEvent::Notification).unwrap(),
+            "permission_prompt"
+        );
+        assert_eq!(matcher_value(HookEvent::SessionStart).unwrap(), "startup");
+        assert_eq!(matcher_value(HookEvent::SubagentStop).unwrap(), "Explore");
+        assert_eq!(matcher_value(HookEvent::Stop), None);
+
+        // Without defaults, only the given fields are set
+        let literal = build_payload(&HookEvent::SessionStart, cwd, &options, false);
+        assert!(literal.get("source").is_none());
+        let literal = build_payload(&HookEvent::PostToolUse, cwd, &options, false);
+        assert_eq!(literal["tool_name"], "Edit");
+        let options = PayloadOptions::default();
+        let literal = build_payload(&HookEvent::PostToolUse, cwd, &options, false);
+        assert!(literal.get("tool_name").is_none());
+        assert!(literal.get("tool_input").is_none());
+    }
+
+    #[test]
+    fn test_parse_unknown_event() {
+        let event = HookEvent::parse("TeammateIdle");
+        let input = HookInput::parse(&event, r#"{"cwd": "/", "extra": 1}"#).unwrap();
+        assert!(matches!(input.payload, EventPayload::Unknown(_)));
+
+        // Known events with malformed fields fall back to the raw input
+        let input = HookInput::parse(
+            &HookEvent::Stop,
+            r#"{"cwd": "/", "stop_hook_active": "yes"}"#,
+        )
+        .unwrap();
+        assert!(matches!(input.payload, EventPayload::Unknown(_)));
+    }
+}
@@ -1,31 +1,62 @@
 //! History tracking for hassha melody playback.
 //!
-//! Maintains a log of the last N played melodies and triggered events.
-//! History is stored in `~/.hassha/history.json`.
+//! History is an append-only JSONL log at `~/.hassha/history.jsonl`, oldest
+//! entry first. Writers take an advisory lock on `~/.hassha/history.lock`, so
+//! concurrent hooks never lose entries. The log is compacted according to the
+//! `[history]` retention settings in the user config once it grows past its
+//! retention, and reading recent entries only reads the end of the file.
+//!
+//! A legacy `~/.hassha/history.json` is migrated on first use.
 
 use anyhow::{Context, Result};
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat, TimeDelta, Utc};
+
+use crate::config::{HistoryConfig, load_user_config, parse_duration};
+use crate::listing::csv_field;
+use crate::selector::glob_match;
+
+/// Default number of entries kept by compaction
+pub const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+/// Default number of entries shown by `hassha history`
+pub const DEFAULT_DISPLAY_ENTRIES: usize = 10;
 
-/// Maximum number of history entries to keep
-const MAX_HISTORY_ENTRIES: usize = 10;
+/// Compact once the log is this many bytes per retained entry on average
+const COMPACT_BYTES_PER_ENTRY: u64 = 512;
 
-/// A single history entry
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A single history entry, recorded each time a hook fires
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct HistoryEntry {
-    /// Timestamp when the melody was played (ISO 8601 format)
-    pub timestamp: String,
+    /// When the hook fired, as an RFC 3339 timestamp with the local offset
+    /// and millisecond precision
+    #[serde(serialize_with = "serialize_timestamp")]
+    pub timestamp: DateTime<FixedOffset>,
 
-    /// The hook event that triggered playback
+    /// The hook event that fired
     pub event: String,
 
-    /// The melody that was played (ID, URL, or path)
+    /// The melody that was played (ID, URL, or path), or that would have
+    /// been played. Empty when no rule applies.
+    #[serde(default)]
     pub melody: String,
 
     /// The project directory where the event occurred
     pub project_dir: String,
 
+    /// Optional: the agent session the event belongs to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+
     /// Optional: tool name for tool-related events
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_name: Option<String>,
@@ -33,163 +64,689 @@ pub struct HistoryEntry {
     /// Optional: matcher that was used
     #[serde(skip_serializing_if = "Option::is_none")]
     pub matcher: Option<String>,
+
+    /// What happened when the hook fired
+    #[serde(default)]
+    pub outcome: Outcome,
+
+    /// Optional: the error, for the `error` outcome
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// Optional: the config file that was used
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_path: Option<String>,
+}
+
+impl HistoryEntry {
+    /// A new entry for an event firing now
+    pub fn new(event: &str, project_dir: &str, outcome: Outcome) -> Self {
+        Self {
+            timestamp: Local::now().fixed_offset(),
+            event: event.to_string(),
+            melody: String::new(),
+            project_dir: project_dir.to_string(),
+            session_id: None,
+            tool_name: None,
+            matcher: None,
+            outcome,
+            error: None,
+            config_path: None,
+        }
+    }
 }
 
-/// The history log structure
+/// What happened when a hook fired
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    /// The melody was played
+    #[default]
+    Played,
+    /// No config file was found for the project
+    NoConfig,
+    /// The config has no rule for the event
+    NoRule,
+    /// The rule's matcher didn't match
+    MatcherMiss,
+    /// The rule's volume is zero
+    Muted,
+    /// Skipped because another melody was playing
+    Overlap,
+    /// Skipped because the hook played too recently
+    Cooldown,
+    /// Skipped because the agent's turn was shorter than `min_turn_duration`
+    ShortTurn,
+    /// Resolving or playing the melody failed
+    Error,
+}
+
+impl Outcome {
+    /// Check whether the melody was played
+    pub fn is_played(self) -> bool {
+        self == Outcome::Played
+    }
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Outcome::Played => "played",
+            Outcome::NoConfig => "no-config",
+            Outcome::NoRule => "no-rule",
+            Outcome::MatcherMiss => "matcher-miss",
+            Outcome::Muted => "muted",
+            Outcome::Overlap => "overlap",
+            Outcome::Cooldown => "cooldown",
+            Outcome::ShortTurn => "short-turn",
+            Outcome::Error => "error",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Recent history entries, newest first
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct History {
     pub entries: Vec<HistoryEntry>,
 }
 
+/// History retention limits
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Retention {
+    /// Keep at most this many entries
+    pub max_entries: usize,
+    /// Drop entries older than this
+    pub max_age: Option<Duration>,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_age: None,
+        }
+    }
+}
+
+impl From<&HistoryConfig> for Retention {
+    fn from(config: &HistoryConfig) -> Self {
+        Self {
+            max_entries: config.max_entries.unwrap_or(DEFAULT_MAX_ENTRIES),
+            max_age: config.max_age,
+        }
+    }
+}
+
+impl Retention {
+    /// Load retention settings from the user config
+    pub fn load() -> Self {
+        load_user_config()
+            .ok()
+            .flatten()
+            .map(|config| Retention::from(&config.history))
+            .unwrap_or_default()
+    }
+
+    /// Whether an entry is older than `max_age` at `now`. A `max_age` too
+    /// large to add to the entry's time never expires it.
+    fn is_expired(&self, entry: &HistoryEntry, now: DateTime<Utc>) -> bool {
+        let Some(max_age) = self.max_age.and_then(|age| TimeDelta::from_std(age).ok()) else {
+            return false;
+        };
+        entry
+            .timestamp
+            .checked_add_signed(max_age)
+            .is_some_and(|expiry| expiry < now)
+    }
+
+    /// Apply the limits to entries in log order (oldest first)
+    pub fn apply(&self, entries: Vec<HistoryEntry>, now: DateTime<Utc>) -> Vec<HistoryEntry> {
+        let mut entries: Vec<_> = entries
+            .into_iter()
+            .filter(|entry| !self.is_expired(entry, now))
+            .collect();
+
+        let excess = entries.len().saturating_sub(self.max_entries);
+        entries.drain(..excess);
+        entries
+    }
+}
+
+fn hassha_dir() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    Ok(home.join(".hassha"))
+}
+
 /// Get the path to the history file
 pub fn history_file_path() -> Result<PathBuf> {
-    let home = dirs::home_dir().context("Could not determine home directory")?;
-    Ok(home.join(".hassha").join("history.json"))
+    Ok(hassha_dir()?.join("history.jsonl"))
 }
 
-/// Load the history from disk
-pub fn load_history() -> Result<History> {
-    let path = history_file_path()?;
+/// The history file used before the JSONL log
+fn legacy_history_path() -> Result<PathBuf> {
+    Ok(hassha_dir()?.join("history.json"))
+}
 
+/// Take the history lock, shared for readers or exclusive for writers.
+/// The lock is released when the returned file is dropped.
+fn lock_history(exclusive: bool) -> Result<File> {
+    crate::state::lock(&hassha_dir()?.join("history.lock"), exclusive)
+}
+
+/// Filters for `hassha history`. All set filters must match.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    /// Hook event name (case-insensitive)
+    pub event: Option<String>,
+    /// Project directory; entries from subdirectories match too
+    pub project: Option<PathBuf>,
+    /// Only entries at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Melody pattern, where `*` matches any characters (e.g., "JY-*")
+    pub melody: Option<String>,
+    /// Only entries with this outcome
+    pub outcome: Option<Outcome>,
+    /// Session ID or a prefix of it
+    pub session: Option<String>,
+}
+
+impl HistoryFilter {
+    /// Check whether an entry matches all set filters
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.event
+            .as_ref()
+            .is_none_or(|event| entry.event.eq_ignore_ascii_case(event))
+            && self
+                .project
+                .as_ref()
+                .is_none_or(|project| Path::new(&entry.project_dir).starts_with(project))
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.outcome.is_none_or(|outcome| entry.outcome == outcome)
+            && self.session.as_ref().is_none_or(|session| {
+                entry
+                    .session_id
+                    .as_ref()
+                    .is_some_and(|id| id.starts_with(session.as_str()))
+            })
+            && self.melody.as_ref().is_none_or(|pattern| {
+                // Sequences are logged as "A + B"; any part may match
+                glob_match(pattern, &entry.melody)
+                    || entry
+                        .melody
+                        .split(" + ")
+                        .any(|part| glob_match(pattern, part))
+            })
+    }
+}
+
+/// Parse a `--since` value: a duration before now (e.g., "1h", "7d") or an
+/// RFC 3339 timestamp
+pub fn parse_since(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.to_utc());
+    }
+
+    let duration = parse_duration(value).with_context(|| {
+        format!(
+            "Invalid --since value {:?}: use a duration (e.g., 1h) or an RFC 3339 timestamp",
+            value
+        )
+    })?;
+    Ok(now - TimeDelta::from_std(duration)?)
+}
+
+/// Load the most recent entries matching `filter`, newest first.
+/// With a `limit` of `None`, every matching entry is returned.
+pub fn load_history(filter: &HistoryFilter, limit: Option<usize>) -> Result<History> {
+    migrate_legacy_history()?;
+
+    let path = history_file_path()?;
+    let _lock = lock_history(false)?;
     if !path.exists() {
         return Ok(History::default());
     }
 
-    let content = fs::read_to_string(&path)
+    let file = File::open(&path)
         .with_context(|| format!("Failed to read history file: {}", path.display()))?;
+    let entries = read_matching(&file, filter, limit)?;
+
+    Ok(History { entries })
+}
 
-    let history: History = serde_json::from_str(&content)
-        .with_context(|| format!("Failed to parse history file: {}", path.display()))?;
+/// Read the most recent unexpired entries of the log matching `filter`,
+/// newest first. Requires the history lock.
+fn read_matching(
+    file: &File,
+    filter: &HistoryFilter,
+    limit: Option<usize>,
+) -> Result<Vec<HistoryEntry>> {
+    let retention = Retention::load();
+    let now = Utc::now();
 
-    Ok(history)
+    read_recent(file, limit, |entry| {
+        !retention.is_expired(entry, now) && filter.matches(entry)
+    })
 }
 
-/// Save the history to disk
-pub fn save_history(history: &History) -> Result<()> {
-    let path = history_file_path()?;
+/// Read the most recent entries of a JSONL log matching `filter`, newest
+/// first, reading the file backwards so only the needed tail is read
+pub fn read_recent<R: Read + Seek>(
+    mut reader: R,
+    limit: Option<usize>,
+    filter: impl Fn(&HistoryEntry) -> bool,
+) -> Result<Vec<HistoryEntry>> {
+    const BLOCK: u64 = 8192;
+
+    let mut position = reader.seek(SeekFrom::End(0))?;
+    // The start of the region read so far, which may be a partial line
+    let mut partial: Vec<u8> = Vec::new();
+    let mut entries = Vec::new();
+
+    while position > 0 {
+        let read = BLOCK.min(position);
+        position -= read;
 
-    // Ensure the directory exists
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
+        let mut data = vec![0; read as usize];
+        reader.seek(SeekFrom::Start(position))?;
+        reader.read_exact(&mut data)?;
+        data.extend(partial);
+
+        // Unless at the start of the file, the first line may be incomplete
+        let complete = if position > 0 {
+            match data.iter().position(|&b| b == b'\n') {
+                Some(index) => {
+                    let rest = data.split_off(index + 1);
+                    partial = data;
+                    rest
+                }
+                None => {
+                    partial = data;
+                    continue;
+                }
+            }
+        } else {
+            partial = Vec::new();
+            data
+        };
+
+        let text = String::from_utf8_lossy(&complete);
+        for entry in parse_lines(text.lines().rev()) {
+            if filter(&entry) {
+                entries.push(entry);
+                if limit.is_some_and(|limit| entries.len() >= limit) {
+                    return Ok(entries);
+                }
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Parse JSONL lines, skipping blank and corrupt lines (e.g., a line cut
+/// short by a crash)
+fn parse_lines<'a>(lines: impl Iterator<Item = &'a str>) -> Vec<HistoryEntry> {
+    lines
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Read every entry in the log, oldest first
+fn read_all(path: &std::path::Path) -> Result<Vec<HistoryEntry>> {
+    if !path.exists() {
+        return Ok(Vec::new());
     }
 
-    let content = serde_json::to_string_pretty(history)?;
-    fs::write(&path, content)?;
+    let file = File::open(path)
+        .with_context(|| format!("Failed to read history file: {}", path.display()))?;
+    let lines: Vec<String> = BufReader::new(file).lines().collect::<Result<_, _>>()?;
+    Ok(parse_lines(lines.iter().map(String::as_str)))
+}
+
+/// Replace the log with `entries` atomically. Requires the exclusive lock.
+fn rewrite(path: &std::path::Path, entries: &[HistoryEntry]) -> Result<()> {
+    let tmp_path = path.with_extension("jsonl.tmp");
+    let mut content = String::new();
+    for entry in entries {
+        content.push_str(&serde_json::to_string(entry)?);
+        content.push('\n');
+    }
+
+    fs::write(&tmp_path, content)
+        .with_context(|| format!("Failed to write history file: {}", tmp_path.display()))?;
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to write history file: {}", path.display()))?;
 
     Ok(())
 }
 
-/// Add a new entry to the history
-pub fn add_history_entry(
-    event: &str,
-    melody: &str,
-    project_dir: &str,
-    tool_name: Option<&str>,
-    matcher: Option<&str>,
-) -> Result<()> {
-    let mut history = load_history().unwrap_or_default();
-
-    // Get current timestamp in ISO 8601 format
-    let timestamp = chrono_lite_timestamp();
-
-    let entry = HistoryEntry {
-        timestamp,
-        event: event.to_string(),
-        melody: melody.to_string(),
-        project_dir: project_dir.to_string(),
-        tool_name: tool_name.map(|s| s.to_string()),
-        matcher: matcher.map(|s| s.to_string()),
+/// Move entries from a legacy `history.json` into the JSONL log. A legacy
+/// file that can't be parsed is kept as `history.json.bak` instead.
+fn migrate_legacy_history() -> Result<()> {
+    let legacy = legacy_history_path()?;
+    if !legacy.exists() {
+        return Ok(());
+    }
+
+    let _lock = lock_history(true)?;
+    // Another process may have migrated while we waited for the lock
+    if !legacy.exists() {
+        return Ok(());
+    }
+
+    let content = fs::read_to_string(&legacy)
+        .with_context(|| format!("Failed to read history file: {}", legacy.display()))?;
+    let history: History = match serde_json::from_str(&content) {
+        Ok(history) => history,
+        Err(e) => {
+            let backup = legacy.with_extension("json.bak");
+            log::warn!(
+                "Failed to parse legacy history, moving it to {}: {}",
+                backup.display(),
+                e
+            );
+            return fs::rename(&legacy, &backup)
+                .with_context(|| format!("Failed to move legacy history: {}", legacy.display()));
+        }
     };
 
-    // Add to the front
-    history.entries.insert(0, entry);
+    // Legacy entries are newest first and older than anything in the log
+    let path = history_file_path()?;
+    let mut entries: Vec<_> = history.entries.into_iter().rev().collect();
+    entries.extend(read_all(&path)?);
+    rewrite(&path, &entries)?;
+
+    fs::remove_file(&legacy)
+        .with_context(|| format!("Failed to remove legacy history: {}", legacy.display()))
+}
 
-    // Trim to max entries
-    history.entries.truncate(MAX_HISTORY_ENTRIES);
+/// Add a new entry to the history
+pub fn add_history_entry(entry: &HistoryEntry) -> Result<()> {
+    migrate_legacy_history()?;
 
-    save_history(&history)?;
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+
+    let path = history_file_path()?;
+    let _lock = lock_history(true)?;
+
+    let len = {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open history file: {}", path.display()))?;
+        file.write_all(line.as_bytes())?;
+        file.metadata()?.len()
+    };
+
+    let retention = Retention::load();
+    if len > compact_threshold(&retention) {
+        compact_locked(&retention)?;
+    }
 
     Ok(())
 }
 
-/// Get a simple timestamp without external dependencies
-fn chrono_lite_timestamp() -> String {
-    use std::time::{SystemTime, UNIX_EPOCH};
+/// Log size in bytes above which appending triggers compaction
+fn compact_threshold(retention: &Retention) -> u64 {
+    (retention.max_entries.max(1) as u64).saturating_mul(COMPACT_BYTES_PER_ENTRY)
+}
 
-    let duration = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default();
+/// Result of a history compaction
+pub struct CompactStats {
+    pub before: usize,
+    pub after: usize,
+}
 
-    let secs = duration.as_secs();
+/// Apply the retention settings from the user config to the log
+pub fn compact_history() -> Result<CompactStats> {
+    migrate_legacy_history()?;
 
-    // Convert to rough datetime (not timezone aware, but good enough for logging)
-    let days_since_epoch = secs / 86400;
-    let time_of_day = secs % 86400;
+    let _lock = lock_history(true)?;
+    compact_locked(&Retention::load())
+}
 
-    let hours = time_of_day / 3600;
-    let minutes = (time_of_day % 3600) / 60;
-    let seconds = time_of_day % 60;
+/// Compact the log. Requires the exclusive lock.
+fn compact_locked(retention: &Retention) -> Result<CompactStats> {
+    let path = history_file_path()?;
+    let entries = read_all(&path)?;
+    let before = entries.len();
 
-    // Approximate date calculation (not accounting for leap years perfectly)
-    let mut year = 1970;
-    let mut remaining_days = days_since_epoch;
+    let entries = retention.apply(entries, Utc::now());
+    let after = entries.len();
+    if path.exists() {
+        rewrite(&path, &entries)?;
+    }
+
+    Ok(CompactStats { before, after })
+}
 
-    loop {
-        let days_in_year = if is_leap_year(year) { 366 } else { 365 };
-        if remaining_days < days_in_year {
-            break;
+/// Delete the history. Returns whether there was any.
+pub fn clear_history() -> Result<bool> {
+    let _lock = lock_history(true)?;
+
+    let mut cleared = false;
+    for path in [history_file_path()?, legacy_history_path()?] {
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove history: {}", path.display()))?;
+            cleared = true;
         }
-        remaining_days -= days_in_year;
-        year += 1;
     }
 
-    let (month, day) = days_to_month_day(remaining_days as u32, is_leap_year(year));
+    Ok(cleared)
+}
+
+fn serialize_timestamp<S>(
+    timestamp: &DateTime<FixedOffset>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&timestamp.to_rfc3339_opts(SecondsFormat::Millis, false))
+}
 
+/// Format a timestamp in local time with a relative time, e.g.
+/// "2026-10-18 18:21:03, 3 min ago"
+pub fn format_timestamp(timestamp: &DateTime<FixedOffset>, now: DateTime<Utc>) -> String {
     format!(
-        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
-        year, month, day, hours, minutes, seconds
+        "{}, {}",
+        timestamp.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+        format_relative(now.signed_duration_since(timestamp))
     )
 }
 
-fn is_leap_year(year: u64) -> bool {
-    (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0)
+/// Format an elapsed time, e.g. "just now", "3 min ago" or "2 d ago"
+pub fn format_relative(elapsed: TimeDelta) -> String {
+    let seconds = elapsed.num_seconds();
+    match seconds {
+        ..0 => "in the future".to_string(),
+        0..10 => "just now".to_string(),
+        10..60 => format!("{} s ago", seconds),
+        60..3600 => format!("{} min ago", seconds / 60),
+        3600..86400 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} d ago", seconds / 86400),
+    }
 }
 
-fn days_to_month_day(mut day_of_year: u32, leap: bool) -> (u32, u32) {
-    let days_in_months: [u32; 12] = if leap {
-        [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31]
-    } else {
-        [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31]
-    };
+/// Summary of one agent session in the history
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionSummary {
+    pub id: String,
+    pub project_dir: String,
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
+    pub events: usize,
+    pub played: usize,
+}
+
+/// Group entries (in any order) by session, most recently active first.
+/// Entries without a session ID are left out.
+pub fn summarize_sessions(entries: &[HistoryEntry]) -> Vec<SessionSummary> {
+    let mut sessions: Vec<SessionSummary> = Vec::new();
+    let mut index: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+
+    for entry in entries {
+        let Some(id) = entry.session_id.as_deref() else {
+            continue;
+        };
 
-    for (i, &days) in days_in_months.iter().enumerate() {
-        if day_of_year < days {
-            return (i as u32 + 1, day_of_year + 1);
+        let i = *index.entry(id).or_insert_with(|| {
+            sessions.push(SessionSummary {
+                id: id.to_string(),
+                project_dir: entry.project_dir.clone(),
+                start: entry.timestamp,
+                end: entry.timestamp,
+                events: 0,
+                played: 0,
+            });
+            sessions.len() - 1
+        });
+
+        let session = &mut sessions[i];
+        session.start = session.start.min(entry.timestamp);
+        session.end = session.end.max(entry.timestamp);
+        session.events += 1;
+        if entry.outcome.is_played() {
+            session.played += 1;
         }
-        day_of_year -= days;
     }
 
-    (12, 31) // fallback
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.end));
+    sessions
+}
+
+/// Format session summaries for display
+pub fn format_sessions(sessions: &[SessionSummary]) -> String {
+    if sessions.is_empty() {
+        return "No sessions in history yet.\n".to_string();
+    }
+
+    let now = Utc::now();
+    let mut output = String::new();
+
+    for session in sessions {
+        let start = session.start.with_timezone(&Local);
+        let end = session.end.with_timezone(&Local);
+        // Only repeat the date when the session spans midnight
+        let end = if start.date_naive() == end.date_naive() {
+            end.format("%H:%M:%S").to_string()
+        } else {
+            end.format("%Y-%m-%d %H:%M:%S").to_string()
+        };
+
+        output.push_str(&format!("{}\n", session.id));
+        output.push_str(&format!(
+            "   {} - {} ({})\n",
+            start.format("%Y-%m-%d %H:%M:%S"),
+            end,
+            format_relative(now - session.end.to_utc())
+        ));
+        output.push_str(&format!(
+            "   {} events, {} played\n",
+            session.events, session.played
+        ));
+        output.push_str(&format!("   Project: {}\n\n", session.project_dir));
+    }
+
+    output
+}
+
+/// Output format for `hassha history`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum HistoryFormat {
+    /// Human-readable list
+    #[default]
+    Table,
+    /// JSON array, newest first
+    Json,
+    /// CSV with a header row, newest first
+    Csv,
+}
+
+/// Format history in the given format
+pub fn format_history_as(history: &History, format: HistoryFormat) -> String {
+    match format {
+        HistoryFormat::Table => format_history(history),
+        HistoryFormat::Json => {
+            let mut output = serde_json::to_string_pretty(&history.entries).unwrap_or_default();
+            output.push('\n');
+            output
+        }
+        HistoryFormat::Csv => format_history_csv(history),
+    }
+}
+
+/// Format history as CSV with a header row
+pub fn format_history_csv(history: &History) -> String {
+    let mut output = String::from(
+        "timestamp,event,melody,project_dir,session_id,tool_name,matcher,outcome,error,config_path\n",
+    );
+
+    for entry in &history.entries {
+        let timestamp = entry
+            .timestamp
+            .to_rfc3339_opts(SecondsFormat::Millis, false);
+        let fields = [
+            timestamp.as_str(),
+            &entry.event,
+            &entry.melody,
+            &entry.project_dir,
+            entry.session_id.as_deref().unwrap_or(""),
+            entry.tool_name.as_deref().unwrap_or(""),
+            entry.matcher.as_deref().unwrap_or(""),
+            &entry.outcome.to_string(),
+            entry.error.as_deref().unwrap_or(""),
+            entry.config_path.as_deref().unwrap_or(""),
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        output.push_str(&row.join(","));
+        output.push('\n');
+    }
+
+    output
 }
 
 /// Format history for display
 pub fn format_history(history: &History) -> String {
     if history.entries.is_empty() {
-        return "No melody history yet.".to_string();
+        return "No melody history yet.\n".to_string();
     }
 
+    let now = Utc::now();
     let mut output = String::new();
-    output.push_str("Recent melody history (last 10):\n\n");
+    output.push_str(&format!(
+        "Recent melody history (last {}):\n\n",
+        history.entries.len()
+    ));
 
     for (i, entry) in history.entries.iter().enumerate() {
         output.push_str(&format!(
             "{}. [{}] {}\n",
             i + 1,
-            entry.timestamp,
+            format_timestamp(&entry.timestamp, now),
             entry.event
         ));
-        output.push_str(&format!("   Melody: {}\n", entry.melody));
+        if !entry.melody.is_empty() {
+            output.push_str(&format!("   Melody: {}\n", entry.melody));
+        }
+        if !entry.outcome.is_played() {
+            output.push_str(&format!("   Outcome: {}\n", entry.outcome));
+        }
+        if let Some(error) = &entry.error {
+            output.push_str(&format!("   Error: {}\n", error));
+        }
         output.push_str(&format!("   Project: {}\n", entry.project_dir));
+        if let Some(config_path) = &entry.config_path {
+            output.push_str(&format!("   Config: {}\n", config_path));
+        }
+        if let Some(session_id) = &entry.session_id {
+            output.push_str(&format!("   Session: {}\n", session_id));
+        }
 
         if let Some(tool) = &entry.tool_name {
             output.push_str(&format!("   Tool: {}\n", tool));
@@ -204,47 +761,484 @@ pub fn format_history(history: &History) -> String {
     output
 }
 
+/// Whether to color terminal output: stdout is a terminal and `NO_COLOR`
+/// is not set
+pub fn use_color() -> bool {
+    std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+/// Wrap text in an ANSI color code when `color` is set
+fn paint(text: &str, code: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// ANSI color for a hook event
+fn event_color(event: &str) -> &'static str {
+    match event {
+        "Stop" | "SubagentStop" => "32",
+        "Notification" | "PermissionRequest" => "33",
+        "PreToolUse" | "PostToolUse" | "PostToolUseFailure" => "36",
+        "SessionStart" | "SessionEnd" | "UserPromptSubmit" | "PreCompact" => "35",
+        _ => "34",
+    }
+}
+
+/// ANSI color for an outcome
+fn outcome_color(outcome: Outcome) -> &'static str {
+    match outcome {
+        Outcome::Played => "32",
+        Outcome::Error => "1;31",
+        Outcome::Muted | Outcome::Overlap | Outcome::Cooldown | Outcome::ShortTurn => "33",
+        Outcome::NoConfig | Outcome::NoRule | Outcome::MatcherMiss => "2",
+    }
+}
+
+/// Format an entry as a single line, for `hassha history --follow`
+pub fn format_entry_line(entry: &HistoryEntry, color: bool) -> String {
+    let event = match &entry.tool_name {
+        Some(tool) => format!("{}({})", entry.event, tool),
+        None => entry.event.clone(),
+    };
+
+    let mut line = format!(
+        "{}  {}  {}  {}  {}",
+        entry.timestamp.with_timezone(&Local).format("%H:%M:%S"),
+        paint(&format!("{:<24}", event), event_color(&entry.event), color),
+        paint(
+            &format!("{:<12}", entry.outcome.to_string()),
+            outcome_color(entry.outcome),
+            color
+        ),
+        if entry.melody.is_empty() {
+            "-"
+        } else {
+            &entry.melody
+        },
+        paint(&entry.project_dir, "2", color),
+    );
+    if let Some(error) = &entry.error {
+        line.push_str(&format!("\n          {}", paint(error, "31", color)));
+    }
+
+    line
+}
+
+/// How often `hassha history --follow` checks the log for new entries
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Identifies the log file, to notice compaction replacing it even when
+/// the new log is as long as the old one
+fn file_identity(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Some((metadata.dev(), metadata.ino()))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        None
+    }
+}
+
+/// Print the most recent `limit` entries matching `filter`, then stream new
+/// ones as hooks append them until Ctrl-C
+pub fn follow_history(filter: &HistoryFilter, limit: Option<usize>, color: bool) -> Result<()> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = Arc::clone(&stop);
+    ctrlc::set_handler(move || handler_stop.store(true, Ordering::SeqCst))
+        .context("Failed to install Ctrl-C handler")?;
+
+    migrate_legacy_history()?;
+    let path = history_file_path()?;
+    let mut state = FollowState::default();
+
+    // Read the recent entries and where they end under the lock, so no
+    // entry appended meanwhile is shown twice or skipped
+    let (mut position, mut identity, mut recent) = {
+        let _lock = lock_history(false)?;
+        match File::open(&path) {
+            Ok(file) => {
+                let metadata = file.metadata()?;
+                let recent = read_matching(&file, filter, limit)?;
+                state.seen.extend(read_all(&path)?);
+                (metadata.len(), file_identity(&metadata), recent)
+            }
+            Err(_) => (0, None, Vec::new()),
+        }
+    };
+    recent.reverse();
+    for entry in &recent {
+        println!("{}", format_entry_line(entry, color));
+    }
+
+    let mut partial: Vec<u8> = Vec::new();
+
+    while !stop.load(Ordering::SeqCst) {
+        std::thread::sleep(FOLLOW_INTERVAL);
+
+        let Ok(mut file) = File::open(&path) else {
+            // Cleared; follow the log once it's recreated
+            position = 0;
+            state = FollowState::default();
+            continue;
+        };
+        let metadata = file.metadata()?;
+        let len = metadata.len();
+        if len < position || file_identity(&metadata) != identity {
+            // Compacted or cleared and rewritten: read it again from the start
+            position = 0;
+            partial.clear();
+            state.restart();
+            identity = file_identity(&metadata);
+        }
+        if len == position {
+            continue;
+        }
+
+        file.seek(SeekFrom::Start(position))?;
+        position += file.read_to_end(&mut partial)? as u64;
+
+        // A hook may be in the middle of appending a line
+        let Some(end) = partial.iter().rposition(|&b| b == b'\n') else {
+            continue;
+        };
+        let complete: Vec<u8> = partial.drain(..=end).collect();
+        let text = String::from_utf8_lossy(&complete);
+
+        for entry in state.unseen(parse_lines(text.lines())) {
+            if filter.matches(&entry) {
+                println!("{}", format_entry_line(&entry, color));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The entries `hassha history --follow` has read from the log.
+///
+/// Compaction rewrites the log, keeping some of the entries already read.
+/// They are recognized by content rather than by timestamp, since entries
+/// are appended when a hook finishes, not in the order hooks fired.
+#[derive(Default)]
+struct FollowState {
+    /// Entries read from the current log
+    seen: HashSet<HistoryEntry>,
+    /// Entries read from the log before it was compacted
+    compacted: HashSet<HistoryEntry>,
+}
+
+impl FollowState {
+    /// Start reading a compacted log from the beginning
+    fn restart(&mut self) {
+        self.compacted = std::mem::take(&mut self.seen);
+    }
+
+    /// Record entries read from the log, returning those not read before
+    fn unseen(&mut self, entries: Vec<HistoryEntry>) -> Vec<HistoryEntry> {
+        let unseen = entries
+            .into_iter()
+            .filter(|entry| {
+                let read_before = self.compacted.remove(entry);
+                self.seen.insert(entry.clone()) && !read_before
+            })
+            .collect();
+        // A compacted log is rewritten in full, so its kept entries come
+        // first and have all been read now
+        self.compacted.clear();
+        unseen
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Cursor;
+
+    fn timestamp(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    fn entry(at: &str, melody: &str) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: timestamp(at),
+            event: "Stop".to_string(),
+            melody: melody.to_string(),
+            project_dir: "/test".to_string(),
+            session_id: None,
+            tool_name: None,
+            matcher: None,
+            outcome: Outcome::Played,
+            error: None,
+            config_path: None,
+        }
+    }
+
+    fn jsonl(entries: &[HistoryEntry]) -> String {
+        entries
+            .iter()
+            .map(|e| serde_json::to_string(e).unwrap() + "\n")
+            .collect()
+    }
 
     #[test]
     fn test_history_entry_serialization() {
         let entry = HistoryEntry {
-            timestamp: "2024-01-15T10:30:00Z".to_string(),
+            timestamp: timestamp("2024-01-15T19:30:00.250+09:00"),
             event: "Stop".to_string(),
             melody: "JY-Shibuya".to_string(),
             project_dir: "/home/user/project".to_string(),
+            session_id: None,
             tool_name: None,
             matcher: None,
+            outcome: Outcome::Played,
+            error: None,
+            config_path: None,
         };
 
         let json = serde_json::to_string(&entry).unwrap();
+        assert!(json.contains("\"2024-01-15T19:30:00.250+09:00\""));
         assert!(json.contains("Stop"));
         assert!(json.contains("JY-Shibuya"));
+        assert!(!json.contains('\n'));
     }
 
     #[test]
-    fn test_history_truncation() {
-        let mut history = History::default();
-
-        // Add more than MAX entries
-        for i in 0..15 {
-            history.entries.insert(
-                0,
-                HistoryEntry {
-                    timestamp: format!("2024-01-{:02}T10:00:00Z", i + 1),
-                    event: "Stop".to_string(),
-                    melody: format!("melody-{}", i),
-                    project_dir: "/test".to_string(),
-                    tool_name: None,
-                    matcher: None,
-                },
-            );
-        }
+    fn test_read_recent() {
+        let entries: Vec<_> = (0..2000)
+            .map(|i| entry("2024-01-01T10:00:00Z", &format!("melody-{}", i)))
+            .collect();
+        let log = jsonl(&entries);
+
+        let recent = read_recent(Cursor::new(log.as_bytes()), Some(3), |_| true).unwrap();
+        let melodies: Vec<_> = recent.iter().map(|e| e.melody.as_str()).collect();
+        assert_eq!(melodies, vec!["melody-1999", "melody-1998", "melody-1997"]);
+
+        // Filtered entries don't count towards the limit
+        let recent = read_recent(Cursor::new(log.as_bytes()), Some(2), |e| {
+            e.melody.ends_with('0')
+        })
+        .unwrap();
+        let melodies: Vec<_> = recent.iter().map(|e| e.melody.as_str()).collect();
+        assert_eq!(melodies, vec!["melody-1990", "melody-1980"]);
 
-        history.entries.truncate(MAX_HISTORY_ENTRIES);
+        // Without a limit, every entry is read across block boundaries
+        let all = read_recent(Cursor::new(log.as_bytes()), None, |_| true).unwrap();
+        assert_eq!(all.len(), 2000);
+        assert_eq!(all[1999].melody, "melody-0");
+
+        assert!(
+            read_recent(Cursor::new(b""), Some(10), |_| true)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_follow_after_compaction() {
+        let old = entry("2024-01-01T10:00:00Z", "JY-Tokyo");
+        let short = entry("2024-01-01T10:00:20Z", "synth:door-chime");
+        let long = entry("2024-01-01T10:00:10Z", "JY-Shibuya");
 
-        assert_eq!(history.entries.len(), MAX_HISTORY_ENTRIES);
+        let mut state = FollowState::default();
+        assert_eq!(state.unseen(vec![old.clone(), short.clone()]).len(), 2);
+
+        // The long melody finished after compaction dropped the oldest entry,
+        // and fired before the short one shown already
+        state.restart();
+        let unseen = state.unseen(vec![short.clone(), long.clone()]);
+        assert_eq!(
+            unseen.iter().map(|e| &e.melody).collect::<Vec<_>>(),
+            ["JY-Shibuya"]
+        );
+
+        // Entries kept by one compaction are still known after the next one
+        state.restart();
+        assert!(state.unseen(vec![short, long]).is_empty());
+    }
+
+    #[test]
+    fn test_history_filter() {
+        let mut stop = entry("2024-01-15T10:00:00Z", "JY-Shibuya + JK-Tokyo");
+        stop.project_dir = "/home/user/project/sub".to_string();
+        let mut notification = entry("2024-01-15T12:00:00Z", "JB-Akihabara");
+        notification.event = "Notification".to_string();
+
+        let by_event = HistoryFilter {
+            event: Some("stop".to_string()),
+            ..Default::default()
+        };
+        assert!(by_event.matches(&stop));
+        assert!(!by_event.matches(&notification));
+
+        let by_project = HistoryFilter {
+            project: Some(PathBuf::from("/home/user/project")),
+            ..Default::default()
+        };
+        assert!(by_project.matches(&stop));
+        assert!(!by_project.matches(&notification));
+
+        let by_melody = HistoryFilter {
+            melody: Some("JK-*".to_string()),
+            ..Default::default()
+        };
+        assert!(by_melody.matches(&stop));
+        assert!(!by_melody.matches(&notification));
+
+        let now = timestamp("2024-01-15T13:00:00Z").to_utc();
+        let by_since = HistoryFilter {
+            since: Some(parse_since("2h", now).unwrap()),
+            ..Default::default()
+        };
+        assert!(!by_since.matches(&stop));
+        assert!(by_since.matches(&notification));
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let now = timestamp("2024-01-15T13:00:00Z").to_utc();
+        assert_eq!(
+            parse_since("90m", now).unwrap(),
+            timestamp("2024-01-15T11:30:00Z")
+        );
+        assert_eq!(
+            parse_since("2024-01-15T19:00:00+09:00", now).unwrap(),
+            timestamp("2024-01-15T10:00:00Z")
+        );
+        assert!(parse_since("yesterday", now).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_lines_are_skipped() {
+        let mut log = jsonl(&[entry("2024-01-01T10:00:00Z", "first")]);
+        log.push_str("{\"timestamp\": \"2024-01-\n\n");
+        log.push_str(&jsonl(&[entry("2024-01-01T10:00:01Z", "second")]));
+
+        let recent = read_recent(Cursor::new(log.as_bytes()), Some(10), |_| true).unwrap();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].melody, "second");
+    }
+
+    #[test]
+    fn test_retention() {
+        let entries: Vec<_> = (1..=15)
+            .map(|i| entry(&format!("2024-01-{:02}T10:00:00Z", i), &format!("m{}", i)))
+            .collect();
+        let now = timestamp("2024-01-15T10:00:00Z").to_utc();
+
+        let by_count = Retention {
+            max_entries: 10,
+            max_age: None,
+        };
+        let kept = by_count.apply(entries.clone(), now);
+        assert_eq!(kept.len(), 10);
+        assert_eq!(kept[0].melody, "m6");
+
+        let by_age = Retention {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_age: Some(Duration::from_secs(3 * 86400)),
+        };
+        let kept = by_age.apply(entries.clone(), now);
+        let melodies: Vec<_> = kept.iter().map(|e| e.melody.as_str()).collect();
+        assert_eq!(melodies, vec!["m12", "m13", "m14", "m15"]);
+
+        // An age past the end of time keeps everything
+        let forever = Retention {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_age: Some(Duration::from_secs(1_000_000 * 365 * 86400)),
+        };
+        assert_eq!(forever.apply(entries, now).len(), 15);
+    }
+
+    #[test]
+    fn test_outcome_serialization() {
+        let mut entry = HistoryEntry::new("PostToolUse", "/p", Outcome::MatcherMiss);
+        entry.config_path = Some("/p/.hassha/config.toml".to_string());
+        let json = serde_json::to_string(&entry).unwrap();
+        assert!(json.contains(r#""outcome":"matcher-miss""#));
+        assert!(json.contains(r#""config_path":"/p/.hassha/config.toml""#));
+        assert!(!json.contains("error"));
+
+        let mut failed = HistoryEntry::new("Stop", "/p", Outcome::Error);
+        failed.error = Some("Failed to download".to_string());
+        let parsed: HistoryEntry =
+            serde_json::from_str(&serde_json::to_string(&failed).unwrap()).unwrap();
+        assert_eq!(parsed.outcome, Outcome::Error);
+        assert_eq!(parsed.error, failed.error);
+        assert_eq!(parsed.outcome.to_string(), "error");
+    }
+
+    #[test]
+    fn test_summarize_sessions() {
+        let mut entries: Vec<_> = [
+            ("2024-01-15T10:00:00Z", Some("a")),
+            ("2024-01-15T10:05:00Z", Some("a")),
+            ("2024-01-15T11:00:00Z", Some("b")),
+            ("2024-01-15T12:00:00Z", None),
+        ]
+        .into_iter()
+        .map(|(at, session)| {
+            let mut entry = entry(at, "JY-Shibuya");
+            entry.session_id = session.map(str::to_string);
+            entry
+        })
+        .collect();
+        entries[1].outcome = Outcome::Cooldown;
+
+        let sessions = summarize_sessions(&entries);
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].id, "b");
+        assert_eq!(sessions[1].id, "a");
+        assert_eq!((sessions[1].events, sessions[1].played), (2, 1));
+        assert_eq!(sessions[1].start, timestamp("2024-01-15T10:00:00Z"));
+        assert_eq!(sessions[1].end, timestamp("2024-01-15T10:05:00Z"));
+
+        let by_session = HistoryFilter {
+            session: Some("a".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(entries.iter().filter(|e| by_session.matches(e)).count(), 2);
+    }
+
+    #[test]
+    fn test_format_entry_line() {
+        let mut failed = entry("2024-01-15T10:00:00Z", "JY-Shibuya");
+        failed.tool_name = Some("Bash".to_string());
+        failed.event = "PostToolUse".to_string();
+        failed.outcome = Outcome::Error;
+        failed.error = Some("Failed to download".to_string());
+
+        let plain = format_entry_line(&failed, false);
+        assert!(plain.contains("PostToolUse(Bash)"));
+        assert!(plain.contains("error"));
+        assert!(plain.ends_with("Failed to download"));
+        assert!(!plain.contains('\x1b'));
+
+        let colored = format_entry_line(&failed, true);
+        assert!(colored.contains("\x1b[36mPostToolUse(Bash)"));
+        assert!(colored.contains("\x1b[1;31merror"));
+    }
+
+    #[test]
+    fn test_legacy_timestamps_parse() {
+        let line = r#"{"timestamp":"2024-01-15T10:30:00Z","event":"Stop","melody":"JY-Shibuya","project_dir":"/p"}"#;
+        let entry: HistoryEntry = serde_json::from_str(line).unwrap();
+        assert_eq!(entry.timestamp, timestamp("2024-01-15T19:30:00+09:00"));
+        assert_eq!(entry.outcome, Outcome::Played);
+    }
+
+    #[test]
+    fn test_format_relative() {
+        assert_eq!(format_relative(TimeDelta::seconds(3)), "just now");
+        assert_eq!(format_relative(TimeDelta::seconds(42)), "42 s ago");
+        assert_eq!(format_relative(TimeDelta::seconds(200)), "3 min ago");
+        assert_eq!(format_relative(TimeDelta::hours(5)), "5 h ago");
+        assert_eq!(format_relative(TimeDelta::days(2)), "2 d ago");
+        assert_eq!(format_relative(TimeDelta::seconds(-5)), "in the future");
     }
 }
@@ -1,179 +1,496 @@
 //! Hook event handler for hassha.
 //!
 //! Parses stdin JSON from Claude Code hooks and plays the appropriate melody.
+//! With `--dry-run` (and `hassha explain`), each step of the decision is
+//! printed instead, without playing or recording anything.
+//!
+//! When a rule with a `message` plays, the rendered message is printed as
+//! hook JSON output (`{"systemMessage": ...}`) for the agent to show. The
+//! agent only shows it for hooks registered without `async`.
 
 use anyhow::{Context, Result};
-use serde::Deserialize;
+use std::collections::HashMap;
 use std::io::{self, Read};
-use std::path::PathBuf;
-
-use crate::cache::resolve_melody_path;
-use crate::config::{get_hook_config, load_config};
-use crate::history::add_history_entry;
+use std::time::Duration;
+
+use crate::cache::{explain_source, resolve_audio};
+use crate::config::{MelodySource, find_config_file, get_hook_config, load_config};
+use crate::event::{HookEvent, HookInput};
+use crate::history::{HistoryEntry, Outcome, add_history_entry};
+use crate::melodies::MelodyRegistry;
+use crate::playback::{self, Suppressed};
 use crate::player::play_audio;
+use crate::probe::audio_duration;
+use crate::template;
+use crate::turns::{self, format_turn};
 
-/// Input received from Claude Code hooks via stdin
-#[derive(Debug, Deserialize)]
-pub struct HookInput {
-    /// The current working directory
-    pub cwd: PathBuf,
+/// Assumed playback length when the audio duration cannot be probed
+const UNKNOWN_DURATION: Duration = Duration::from_secs(10);
 
-    /// The hook event name
-    #[allow(dead_code)]
-    pub hook_event_name: String,
+/// Read and parse hook input for `event` from stdin
+pub fn read_hook_input(event: &HookEvent) -> Result<HookInput> {
+    let mut buffer = String::new();
+    io::stdin()
+        .read_to_string(&mut buffer)
+        .context("Failed to read from stdin")?;
 
-    /// Session ID
-    #[serde(default)]
-    #[allow(dead_code)]
-    pub session_id: Option<String>,
+    // Handle empty stdin gracefully
+    if buffer.trim().is_empty() {
+        anyhow::bail!("No input received on stdin");
+    }
 
-    /// Tool name (for tool-related events: PreToolUse, PostToolUse, PostToolUseFailure, PermissionRequest)
-    #[serde(default)]
-    pub tool_name: Option<String>,
+    HookInput::parse(event, &buffer)
+}
 
-    /// Source (for SessionStart: startup, resume, clear, compact)
-    #[serde(default)]
-    pub source: Option<String>,
+/// One hook event being handled: the history entry recording its outcome,
+/// the rule's rendered message, and a step-by-step account for `--dry-run`
+/// and `hassha explain`
+pub struct Run {
+    /// Only explain; don't play, change playback state or write history
+    dry_run: bool,
+    /// Record the run in the history and the playback and turn state
+    record: bool,
+    pub entry: HistoryEntry,
+    /// The message to show in the agent, if the rule has one
+    pub message: Option<String>,
+    steps: Vec<String>,
+}
 
-    /// Notification type (for Notification events)
-    #[serde(default)]
-    pub notification_type: Option<String>,
+impl Run {
+    fn new(event: &HookEvent, input: &HookInput, dry_run: bool, record: bool) -> Self {
+        let mut entry =
+            HistoryEntry::new(event.name(), &input.cwd.to_string_lossy(), Outcome::Played);
+        entry.session_id = input.session_id.clone();
+        entry.tool_name = input.payload.tool_name().map(str::to_string);
+
+        Self {
+            dry_run,
+            record,
+            entry,
+            message: None,
+            steps: Vec::new(),
+        }
+    }
+
+    fn step(&mut self, step: impl Into<String>) {
+        self.steps.push(step.into());
+    }
+
+    /// Stop with an outcome other than playing
+    fn finish(&mut self, outcome: Outcome, step: impl Into<String>) -> Result<()> {
+        self.entry.outcome = outcome;
+        self.step(step);
+        Ok(())
+    }
+
+    /// Stop because the overlap or cooldown policy suppressed the melody
+    fn suppressed(&mut self, event: &str, suppressed: Suppressed) -> Result<()> {
+        let outcome = match suppressed {
+            Suppressed::Overlap => Outcome::Overlap,
+            Suppressed::Cooldown(_) => Outcome::Cooldown,
+        };
+        log::debug!(event = event, reason:% = suppressed; "Playback suppressed");
+        self.finish(outcome, format!("Skipped: {}", suppressed))
+    }
+}
 
-    /// Agent type (for SubagentStart, SubagentStop)
-    #[serde(default)]
-    pub agent_type: Option<String>,
+/// Handle a hook event, recording what happened in the history.
+/// With `dry_run`, explain what would happen instead.
+pub fn handle_hook(event: &HookEvent, dry_run: bool) -> Result<()> {
+    if !event.is_known() && !dry_run {
+        log::warn!("{}", unknown_event_warning(event.name()));
+    }
 
-    /// Reason (for SessionEnd)
-    #[serde(default)]
-    pub reason: Option<String>,
+    // Read input from stdin
+    let input = read_hook_input(event)?;
 
-    /// Trigger (for PreCompact: manual, auto)
-    #[serde(default)]
-    pub trigger: Option<String>,
+    if dry_run {
+        return explain(event, &input);
+    }
 
-    // Other fields we don't need but might be present
-    #[serde(flatten)]
-    pub _extra: serde_json::Value,
+    let run = run_hook(event, &input, true)?;
+    if let Some(message) = run.message {
+        // The JSON itself is not worth showing in the transcript
+        let output = serde_json::json!({ "systemMessage": message, "suppressOutput": true });
+        println!("{}", output);
+    }
+
+    Ok(())
 }
 
-/// Read and parse hook input from stdin
-pub fn read_hook_input() -> Result<HookInput> {
-    let mut buffer = String::new();
-    io::stdin()
-        .read_to_string(&mut buffer)
-        .context("Failed to read from stdin")?;
+/// Handle a hook event with the given input. With `record`, what happened
+/// is recorded in the history, and the playback and turn state are updated.
+pub fn run_hook(event: &HookEvent, input: &HookInput, record: bool) -> Result<Run> {
+    log::info!(
+        event = event.name(),
+        cwd:% = input.cwd.display(),
+        session = input.session_id.as_deref().unwrap_or("");
+        "Handling hook event"
+    );
 
-    // Handle empty stdin gracefully
-    if buffer.trim().is_empty() {
-        anyhow::bail!("No input received on stdin");
+    // Turns start whether or not a rule plays for the prompt itself
+    if record
+        && *event == HookEvent::UserPromptSubmit
+        && let Some(session) = &input.session_id
+        && let Err(e) = turns::start_turn(session, playback::now_millis())
+    {
+        log::warn!("Failed to record turn start: {:#}", e);
+    }
+
+    let mut run = Run::new(event, input, false, record);
+    let result = fire_hook(event, input, &mut run);
+    if let Err(e) = &result {
+        run.entry.outcome = Outcome::Error;
+        run.entry.error = Some(format!("{:#}", e));
     }
+    log::info!(
+        event = event.name(),
+        outcome:% = run.entry.outcome,
+        melody = run.entry.melody.as_str();
+        "Hook finished"
+    );
 
-    let input: HookInput =
-        serde_json::from_str(&buffer).context("Failed to parse hook input JSON")?;
+    // Log to history (ignore errors - history is non-critical)
+    if record {
+        let _ = add_history_entry(&run.entry);
+    }
 
-    Ok(input)
+    result.map(|_| run)
 }
 
-/// Get the matcher value from the hook input based on event type
-fn get_matcher_value(event_name: &str, input: &HookInput) -> Option<String> {
-    match event_name {
-        // Tool-based events match on tool_name
-        "PreToolUse" | "PostToolUse" | "PostToolUseFailure" | "PermissionRequest" => {
-            input.tool_name.clone()
-        }
-        // SessionStart matches on source (startup, resume, clear, compact)
-        "SessionStart" => input.source.clone(),
-        // SessionEnd matches on reason
-        "SessionEnd" => input.reason.clone(),
-        // Notification matches on notification_type
-        "Notification" => input.notification_type.clone(),
-        // Subagent events match on agent_type
-        "SubagentStart" | "SubagentStop" => input.agent_type.clone(),
-        // PreCompact matches on trigger (manual, auto)
-        "PreCompact" => input.trigger.clone(),
-        // UserPromptSubmit and Stop don't support matchers
-        _ => None,
+/// Print, step by step, how a hook event would be handled, without playing
+/// anything or writing history
+pub fn explain(event: &HookEvent, input: &HookInput) -> Result<()> {
+    let mut run = Run::new(event, input, true, false);
+    if !event.is_known() {
+        run.step(unknown_event_warning(event.name()));
+    }
+
+    let result = fire_hook(event, input, &mut run);
+    if let Err(e) = &result {
+        run.entry.outcome = Outcome::Error;
+        run.step(format!("✗ {:#}", e));
     }
+
+    for (i, step) in run.steps.iter().enumerate() {
+        println!("{}. {}", i + 1, step);
+    }
+    println!();
+    println!(
+        "Outcome: {} (dry run: nothing was played or recorded)",
+        run.entry.outcome
+    );
+
+    Ok(())
 }
 
-/// Handle a hook event
-pub fn handle_hook(event_name: &str) -> Result<()> {
-    // Read input from stdin
-    let input = read_hook_input()?;
+/// Play the melody configured for a hook event, if any, recording the
+/// outcome and each step in `run`
+fn fire_hook(event: &HookEvent, input: &HookInput, run: &mut Run) -> Result<()> {
+    let event_name = event.name();
 
     // Load configuration from the project directory
+    let config_path = find_config_file(&input.cwd);
+    run.entry.config_path = config_path.as_ref().map(|path| path.display().to_string());
     let config = match load_config(&input.cwd)? {
         Some(config) => config,
         None => {
-            // No configuration found, silently exit
-            return Ok(());
+            return run.finish(
+                Outcome::NoConfig,
+                format!(
+                    "No {} found in {} or its parent directories",
+                    crate::config::CONFIG_FILE,
+                    input.cwd.display()
+                ),
+            );
         }
     };
+    if let Some(path) = &config_path {
+        run.step(format!("Config: {}", path.display()));
+    }
 
     // Get hook configuration for this event
     let hook_config = match get_hook_config(&config, event_name) {
         Some(config) => config,
         None => {
-            // No configuration for this event, silently exit
-            return Ok(());
+            let mut step = format!("No [hooks.{}] rule in the config", event_name);
+            if let Some(key) = config
+                .hooks
+                .keys()
+                .find(|key| key.eq_ignore_ascii_case(event_name))
+            {
+                step.push_str(&format!(" ([hooks.{}] differs in case)", key));
+            }
+            return run.finish(Outcome::NoRule, step);
         }
     };
+    run.entry.melody = hook_config.melody.to_string();
+    run.entry.matcher = hook_config.matcher.clone();
+    run.step(format!(
+        "Rule: [hooks.{}] with melody {}",
+        event_name, hook_config.melody
+    ));
 
     // Check matcher if configured
-    if let Some(matcher) = &hook_config.matcher {
-        let matcher_value = get_matcher_value(event_name, &input);
-        let value = matcher_value.as_deref().unwrap_or("");
+    let value = input.payload.matcher_value();
+    match &hook_config.matcher {
+        Some(matcher) => {
+            let alternative = matching_alternative(matcher, value.unwrap_or(""));
+            log::debug!(
+                matcher = matcher.as_str(),
+                value = value.unwrap_or(""),
+                matched = alternative.is_some();
+                "Checked matcher"
+            );
+            let Some(alternative) = alternative else {
+                return run.finish(
+                    Outcome::MatcherMiss,
+                    match value {
+                        Some(value) => format!("Matcher {:?} doesn't match {:?}", matcher, value),
+                        None => format!(
+                            "Matcher {:?} doesn't match: {} events have no matcher value",
+                            matcher, event_name
+                        ),
+                    },
+                );
+            };
+            let mut step = format!("Matcher {:?} matches {:?}", matcher, value.unwrap_or(""));
+            if alternative != matcher {
+                step.push_str(&format!(" (via {:?})", alternative));
+            }
+            run.step(step);
+        }
+        None => run.step("No matcher: every event matches"),
+    }
+
+    // Check how long the agent has been working, and pick its melody
+    let mut turn = None;
+    let bucketed;
+    let mut hook_config = hook_config;
+    if hook_config.min_turn_duration.is_some() || !hook_config.turns.is_empty() {
+        if let Some(session) = &input.session_id {
+            turn = turns::load_turns()?.elapsed(session, playback::now_millis());
+        }
+        match (turn, hook_config.min_turn_duration) {
+            (None, Some(_)) => {
+                return run.finish(
+                    Outcome::ShortTurn,
+                    "Turn length unknown: no UserPromptSubmit was recorded for this session",
+                );
+            }
+            (Some(turn), Some(min)) if turn < min => {
+                return run.finish(
+                    Outcome::ShortTurn,
+                    format!(
+                        "Turn took {}, shorter than min_turn_duration {}",
+                        format_turn(turn),
+                        format_turn(min)
+                    ),
+                );
+            }
+            (Some(turn), _) => run.step(format!("Turn took {}", format_turn(turn))),
+            (None, None) => run.step("Turn length unknown: using the rule's melody"),
+        }
+
+        if let Some(bucket) = turn.and_then(|turn| hook_config.turn_bucket(turn)) {
+            bucketed = hook_config.with_bucket(bucket);
+            hook_config = &bucketed;
+            run.entry.melody = hook_config.melody.to_string();
+            run.step(format!(
+                "Turns of {} or longer play {}",
+                format_turn(bucket.min_turn_duration),
+                hook_config.melody
+            ));
+        }
+    }
+
+    // Expand presets
+    let melody = hook_config.resolve(&config)?;
+    if hook_config.melody.preset().is_some() {
+        run.step(format!(
+            "Preset {} expands to {}",
+            hook_config.melody, melody.source
+        ));
+    }
+    if melody.volume <= 0.0 {
+        return run.finish(Outcome::Muted, "Volume is 0: muted");
+    }
+    run.step(format!("Volume: {}", melody.volume));
+
+    // Respect the overlap and cooldown policies. They are checked again
+    // when the melody starts.
+    let overlap = hook_config.overlap(&config);
+    let cooldown = hook_config.cooldown(&config);
+    let state = playback::load_state()?;
+    if let Some(suppressed) = state.check(event_name, playback::now_millis(), overlap, cooldown) {
+        return run.suppressed(event_name, suppressed);
+    }
+    if overlap == playback::Overlap::Skip || cooldown.is_some() {
+        run.step("Overlap and cooldown policies allow playing");
+    }
 
-        if !matches_pattern(matcher, value) {
-            return Ok(());
+    // Resolve the melody (or sequence) to a single local file, including
+    // project catalogs
+    let registry = MelodyRegistry::load(Some(&config))?;
+    if run.dry_run {
+        for part in melody.source.parts() {
+            let step = explain_source(part, &registry, melody.selection)?;
+            run.step(step);
+        }
+        if let MelodySource::Sequence { gap, .. } = &melody.source {
+            run.step(format!(
+                "Sequence rendered into one file with a {}ms gap",
+                gap.unwrap_or_default().as_millis()
+            ));
         }
+        if let Some(message) = &hook_config.message {
+            // Selectors are only resolved when played
+            let sources: Vec<String> = melody
+                .source
+                .parts()
+                .into_iter()
+                .map(str::to_string)
+                .collect();
+            let duration = match sources.as_slice() {
+                [single] => registry.get(single).and_then(|info| info.duration()),
+                _ => None,
+            };
+            let vars = message_vars(event, input, &sources, &registry, duration, turn);
+            run.step(format!("Message: {:?}", template::render(message, &vars)));
+        }
+        return run.finish(Outcome::Played, "Would play");
     }
 
-    // Resolve melody to a local file path
-    let audio_path = resolve_melody_path(&hook_config.melody)?;
+    let audio = resolve_audio(&melody.source, &registry, melody.selection)?;
+    run.entry.melody = audio.label();
+
+    // A sequence counts as a single playback
+    let duration = audio_duration(&audio.path)
+        .map(|d| melody.end.map_or(d, |end| d.min(end)))
+        .or(melody.end)
+        .unwrap_or(UNKNOWN_DURATION);
+
+    // Another hook may have started a melody while this one was resolved
+    let until = if run.record {
+        let until = playback::update_state(|state| {
+            let now = playback::now_millis();
+            match state.check(event_name, now, overlap, cooldown) {
+                Some(suppressed) => Err(suppressed),
+                None => Ok(state.start(event_name, now, duration)),
+            }
+        })?;
+        match until {
+            Ok(until) => Some(until),
+            Err(suppressed) => return run.suppressed(event_name, suppressed),
+        }
+    } else {
+        None
+    };
+
+    if let Some(message) = &hook_config.message {
+        let vars = message_vars(
+            event,
+            input,
+            &audio.sources,
+            &registry,
+            Some(duration),
+            turn,
+        );
+        run.message = Some(template::render(message, &vars));
+    }
 
     // Play the audio
-    play_audio(&audio_path, hook_config.volume)?;
+    let result = play_audio(&audio.path, melody.volume, melody.end);
+    if let Some(until) = until {
+        playback::update_state(|state| state.finish(until, playback::now_millis()))?;
+    }
 
-    // Log to history (ignore errors - history is non-critical)
-    let _ = add_history_entry(
-        event_name,
-        &hook_config.melody,
-        &input.cwd.to_string_lossy(),
-        input.tool_name.as_deref(),
-        hook_config.matcher.as_deref(),
-    );
+    result
+}
 
-    Ok(())
+/// Variables for a rule's message template: the metadata of the first
+/// played melody, the playback duration, the turn length and the event's
+/// fields
+fn message_vars(
+    event: &HookEvent,
+    input: &HookInput,
+    sources: &[String],
+    registry: &MelodyRegistry,
+    duration: Option<Duration>,
+    turn: Option<Duration>,
+) -> HashMap<&'static str, String> {
+    let mut vars = HashMap::from([
+        ("melody", sources.join(" + ")),
+        ("event", event.name().to_string()),
+        ("cwd", input.cwd.display().to_string()),
+    ]);
+
+    let info = sources.first().and_then(|source| registry.get(source));
+    if let Some(info) = info {
+        vars.insert("melody_name", info.melody_name.to_string());
+        vars.insert("station", info.station.to_string());
+        vars.insert("station_jp", info.station_jp.to_string());
+        vars.insert("line", info.line.to_string());
+        vars.insert("line_name", info.line_name.to_string());
+        vars.extend(info.credit.map(|credit| ("credit", credit.to_string())));
+    }
+    if let Some(duration) = duration {
+        vars.insert("duration", format!("{:.0}s", duration.as_secs_f64()));
+    }
+    if let Some(turn) = turn {
+        vars.insert("turn", format_turn(turn));
+    }
+
+    let fields = [
+        ("tool", input.payload.tool_name()),
+        ("value", input.payload.matcher_value()),
+        ("session", input.session_id.as_deref()),
+        (
+            "project",
+            input.cwd.file_name().and_then(|name| name.to_str()),
+        ),
+    ];
+    for (name, value) in fields {
+        vars.extend(value.map(|value| (name, value.to_string())));
+    }
+
+    vars
+}
+
+/// Warning for an event name hassha doesn't know
+pub fn unknown_event_warning(name: &str) -> String {
+    match HookEvent::suggest(name) {
+        Some(event) => format!("Unknown hook event {:?} (did you mean {}?)", name, event),
+        None => format!(
+            "Unknown hook event {:?}; its rule is used, but matchers never match",
+            name
+        ),
+    }
 }
 
-/// Simple pattern matching for matcher values
+/// Find the alternative of a matcher pattern that matches a value.
 /// Supports:
 /// - Exact match: "Bash" matches "Bash"
 /// - Pipe-separated alternatives: "Bash|Write" matches "Bash" or "Write"
 /// - Wildcard: "*" matches anything
 /// - Regex-like prefix: "mcp__.*" matches "mcp__memory__create"
-fn matches_pattern(pattern: &str, value: &str) -> bool {
+fn matching_alternative<'a>(pattern: &'a str, value: &str) -> Option<&'a str> {
     // Wildcard matches everything
     if pattern == "*" {
-        return true;
+        return Some(pattern);
     }
 
     // Pipe-separated alternatives
-    if pattern.contains('|') {
-        return pattern
-            .split('|')
-            .any(|p| matches_single_pattern(p.trim(), value));
-    }
-
-    matches_single_pattern(pattern, value)
+    pattern
+        .split('|')
+        .map(str::trim)
+        .find(|p| matches_single_pattern(p, value))
 }
 
 /// Match a single pattern (no pipes)
 fn matches_single_pattern(pattern: &str, value: &str) -> bool {
     // Simple regex-like suffix matching for ".*"
-    if pattern.ends_with(".*") {
-        let prefix = &pattern[..pattern.len() - 2];
+    if let Some(prefix) = pattern.strip_suffix(".*") {
         return value.starts_with(prefix);
     }
 
@@ -185,6 +502,10 @@ fn matches_single_pattern(pattern: &str, value: &str) -> bool {
 mod tests {
     use super::*;
 
+    fn matches_pattern(pattern: &str, value: &str) -> bool {
+        matching_alternative(pattern, value).is_some()
+    }
+
     #[test]
     fn test_matches_pattern() {
         // Exact match
@@ -208,43 +529,11 @@ mod tests {
         // Combined
         assert!(matches_pattern("Bash|mcp__.*", "Bash"));
         assert!(matches_pattern("Bash|mcp__.*", "mcp__test__tool"));
-    }
-
-    #[test]
-    fn test_parse_hook_input() {
-        let json = r#"{
-            "cwd": "/home/user/project",
-            "hook_event_name": "Stop",
-            "session_id": "abc123"
-        }"#;
-
-        let input: HookInput = serde_json::from_str(json).unwrap();
-        assert_eq!(input.cwd, PathBuf::from("/home/user/project"));
-        assert_eq!(input.hook_event_name, "Stop");
-    }
-
-    #[test]
-    fn test_parse_tool_hook_input() {
-        let json = r#"{
-            "cwd": "/home/user/project",
-            "hook_event_name": "PostToolUse",
-            "tool_name": "Bash",
-            "tool_input": {"command": "npm test"}
-        }"#;
 
-        let input: HookInput = serde_json::from_str(json).unwrap();
-        assert_eq!(input.tool_name, Some("Bash".to_string()));
-    }
-
-    #[test]
-    fn test_parse_session_start_input() {
-        let json = r#"{
-            "cwd": "/home/user/project",
-            "hook_event_name": "SessionStart",
-            "source": "startup"
-        }"#;
-
-        let input: HookInput = serde_json::from_str(json).unwrap();
-        assert_eq!(input.source, Some("startup".to_string()));
+        // The matching alternative is reported by `--dry-run`
+        assert_eq!(
+            matching_alternative("Bash | mcp__.*", "mcp__test__tool"),
+            Some("mcp__.*")
+        );
     }
 }
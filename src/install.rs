@@ -281,8 +281,8 @@ mod tests {
         assert!(claude_dir.to_string_lossy().contains(".claude"));
 
         let opencode_dir = InstallTarget::OpenCode.plugin_dir().unwrap();
-        assert!(opencode_dir.ends_with("hassha"));
-        assert!(opencode_dir.to_string_lossy().contains(".opencode"));
+        assert!(opencode_dir.ends_with("opencode"));
+        assert!(opencode_dir.to_string_lossy().contains(".config"));
     }
 
     #[test]
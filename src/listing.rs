@@ -0,0 +1,353 @@
+//! Filtering and formatting for `hassha list`.
+//!
+//! The markdown format generates the melody tables in `MELODIES.md`.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use std::time::Duration;
+
+use crate::cache::{CacheStatus, cache_status};
+use crate::melodies::{MelodyInfo, MelodyRegistry};
+
+/// Output format for `hassha list`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ListFormat {
+    /// Human-readable table
+    #[default]
+    Table,
+    /// JSON array
+    Json,
+    /// CSV with a header row
+    Csv,
+    /// Markdown tables grouped by line, as in MELODIES.md
+    Markdown,
+}
+
+/// Filters for `hassha list`. All set filters must match.
+#[derive(Debug, Clone, Default)]
+pub struct MelodyFilter {
+    /// Line code, exact (case-insensitive)
+    pub line: Option<String>,
+    /// Substring of the English or Japanese station name
+    pub station: Option<String>,
+    /// Substring of the melody name
+    pub melody: Option<String>,
+    /// Substring of any field
+    pub search: Option<String>,
+}
+
+impl MelodyFilter {
+    /// Check whether a melody matches all set filters
+    pub fn matches(&self, melody: &MelodyInfo) -> bool {
+        let contains =
+            |haystack: &str, needle: &str| haystack.to_lowercase().contains(&needle.to_lowercase());
+
+        self.line
+            .as_ref()
+            .is_none_or(|line| melody.line.eq_ignore_ascii_case(line))
+            && self.station.as_ref().is_none_or(|station| {
+                contains(melody.station, station) || contains(melody.station_jp, station)
+            })
+            && self
+                .melody
+                .as_ref()
+                .is_none_or(|name| contains(melody.melody_name, name))
+            && self.search.as_ref().is_none_or(|term| {
+                [
+                    melody.id,
+                    melody.line_name,
+                    melody.station,
+                    melody.station_jp,
+                    melody.melody_name,
+                ]
+                .iter()
+                .any(|field| contains(field, term))
+            })
+    }
+}
+
+/// Format melodies in the given format
+pub fn format_melodies(melodies: &[&MelodyInfo], format: ListFormat) -> String {
+    match format {
+        ListFormat::Table => format_table(melodies),
+        ListFormat::Json => format_json(melodies),
+        ListFormat::Csv => format_csv(melodies),
+        ListFormat::Markdown => format_markdown(melodies),
+    }
+}
+
+/// Format melodies as a fixed-width table, separated by line
+pub fn format_table(melodies: &[&MelodyInfo]) -> String {
+    let mut output = String::new();
+    output.push_str(&format!(
+        "{:<22} {:<15} {:<18} {:<10} Melody\n",
+        "ID", "Line", "Station", "Japanese"
+    ));
+    output.push_str(&format!("{}\n", "-".repeat(85)));
+
+    let mut current_line = "";
+    for melody in melodies {
+        // Add separator between lines
+        if melody.line != current_line {
+            if !current_line.is_empty() {
+                output.push('\n');
+            }
+            current_line = melody.line;
+        }
+        output.push_str(&format!(
+            "{:<22} {:<15} {:<18} {:<10} {}\n",
+            melody.id, melody.line_name, melody.station, melody.station_jp, melody.melody_name
+        ));
+    }
+
+    output
+}
+
+/// Format melodies as a JSON array
+pub fn format_json(melodies: &[&MelodyInfo]) -> String {
+    let entries: Vec<_> = melodies
+        .iter()
+        .map(|melody| {
+            serde_json::json!({
+                "id": melody.id,
+                "line": melody.line,
+                "line_name": melody.line_name,
+                "station": melody.station,
+                "station_jp": melody.station_jp,
+                "melody_name": melody.melody_name,
+                "url": melody.url(),
+                "duration_ms": melody.duration().map(|d| d.as_millis() as u64),
+                "credit": melody.credit,
+                "tags": melody.all_tags(),
+            })
+        })
+        .collect();
+
+    let mut output = serde_json::to_string_pretty(&entries).unwrap_or_default();
+    output.push('\n');
+    output
+}
+
+/// Format melodies as CSV with a header row
+pub fn format_csv(melodies: &[&MelodyInfo]) -> String {
+    let mut output = String::from("id,line,line_name,station,station_jp,melody_name,url\n");
+
+    for melody in melodies {
+        let url = melody.url().unwrap_or_default();
+        let fields = [
+            melody.id,
+            melody.line,
+            melody.line_name,
+            melody.station,
+            melody.station_jp,
+            melody.melody_name,
+            url.as_str(),
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        output.push_str(&row.join(","));
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Quote a CSV field if needed
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Format melodies as markdown tables, one section per line
+pub fn format_markdown(melodies: &[&MelodyInfo]) -> String {
+    let mut output = String::new();
+
+    let mut lines: Vec<&str> = Vec::new();
+    for melody in melodies {
+        if !lines.contains(&melody.line) {
+            lines.push(melody.line);
+        }
+    }
+
+    for (i, line) in lines.iter().enumerate() {
+        let entries: Vec<_> = melodies.iter().filter(|m| m.line == *line).collect();
+
+        if i > 0 {
+            output.push('\n');
+        }
+        output.push_str(&format!(
+            "## {} ({}) - {} melodies\n\n",
+            section_title(entries[0].line_name),
+            line,
+            entries.len()
+        ));
+        // Only declared metadata, so the tables don't depend on what is cached
+        output.push_str("| Melody ID | Station | Japanese | Melody Name | Tags |\n");
+        output.push_str("|-----------|---------|----------|-------------|------|\n");
+
+        for melody in entries {
+            output.push_str(&format!(
+                "| `{}` | {} | {} | {} | {} |\n",
+                melody.id,
+                melody.station,
+                melody.station_jp,
+                melody.melody_name,
+                melody.tags.join(", ")
+            ));
+        }
+    }
+
+    output
+}
+
+/// Format everything known about a melody for `hassha info`
+pub fn format_info(melody: &MelodyInfo, registry: &MelodyRegistry) -> Result<String> {
+    let mut output = String::new();
+    let mut field = |name: &str, value: &str| {
+        output.push_str(&format!("  {:<10} {}\n", format!("{}:", name), value));
+    };
+
+    let duration = melody
+        .duration()
+        .map(format_duration)
+        .unwrap_or_else(|| "unknown (not downloaded yet)".to_string());
+    let tags = melody.all_tags();
+
+    let cache = match cache_status(melody)? {
+        CacheStatus::Local(path) => format!("local file {}", path.display()),
+        CacheStatus::Embedded => "embedded in binary".to_string(),
+        CacheStatus::Cached(path, size) => {
+            format!(
+                "cached at {} ({:.1} KB)",
+                path.display(),
+                size as f64 / 1000.0
+            )
+        }
+        CacheStatus::NotCached => "not cached".to_string(),
+    };
+
+    let shared: Vec<&str> = registry
+        .all()
+        .iter()
+        .filter(|m| m.location == melody.location && m.id != melody.id)
+        .map(|m| m.id)
+        .collect();
+
+    field("Line", &format!("{} ({})", melody.line_name, melody.line));
+    field(
+        "Station",
+        &format!("{} ({})", melody.station, melody.station_jp),
+    );
+    field("Melody", melody.melody_name);
+    field("Credit", melody.credit.unwrap_or("unknown"));
+    field("Duration", &duration);
+    field(
+        "Tags",
+        &if tags.is_empty() {
+            "-".to_string()
+        } else {
+            tags.join(", ")
+        },
+    );
+    field("Source", &melody.location.to_string());
+    field("Cache", &cache);
+    if !shared.is_empty() {
+        field("Also at", &shared.join(", "));
+    }
+
+    Ok(format!("{}\n{}", melody.id, output))
+}
+
+/// Format a duration as seconds, e.g. "6.4s"
+pub fn format_duration(duration: Duration) -> String {
+    format!("{:.1}s", duration.as_secs_f64())
+}
+
+/// Section title for a line, e.g. "Yamanote" -> "Yamanote Line"
+fn section_title(line_name: &str) -> String {
+    if line_name.ends_with("Line") || line_name.ends_with("Express") {
+        line_name.to_string()
+    } else {
+        format!("{} Line", line_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::melodies::MELODIES;
+
+    const GENERATED_BEGIN: &str = "<!-- BEGIN GENERATED: hassha list --format markdown -->\n";
+    const GENERATED_END: &str = "<!-- END GENERATED -->";
+
+    fn builtin() -> Vec<&'static MelodyInfo> {
+        MELODIES.iter().collect()
+    }
+
+    #[test]
+    fn test_melodies_doc_matches_registry() {
+        let doc = include_str!("../MELODIES.md");
+        let start =
+            doc.find(GENERATED_BEGIN).expect("missing generated marker") + GENERATED_BEGIN.len();
+        let end = doc
+            .find(GENERATED_END)
+            .expect("missing generated end marker");
+
+        assert_eq!(
+            &doc[start..end],
+            format_markdown(&builtin()),
+            "MELODIES.md is out of date; regenerate with `hassha list --format markdown`"
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        let melodies = builtin();
+        let count = |filter: MelodyFilter| melodies.iter().filter(|m| filter.matches(m)).count();
+
+        assert_eq!(
+            count(MelodyFilter {
+                line: Some("jk".to_string()),
+                ..Default::default()
+            }),
+            15
+        );
+
+        assert!(
+            melodies
+                .iter()
+                .filter(|m| MelodyFilter {
+                    station: Some("shin".to_string()),
+                    ..Default::default()
+                }
+                .matches(m))
+                .all(|m| m.station.to_lowercase().contains("shin"))
+        );
+
+        assert_eq!(
+            count(MelodyFilter {
+                search: Some("渋谷".to_string()),
+                ..Default::default()
+            }),
+            3
+        );
+
+        assert_eq!(
+            count(MelodyFilter {
+                line: Some("JY".to_string()),
+                melody: Some("SH-".to_string()),
+                ..Default::default()
+            }),
+            3
+        );
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("Tokyo"), "Tokyo");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
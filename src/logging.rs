@@ -0,0 +1,343 @@
+//! Logging and hassha's own log at `~/.hassha/hassha.log`.
+//!
+//! Log events are written with the `log` macros and go to stderr for
+//! interactive commands, or to the log file for `hassha hook`. The level is
+//! set by `-v`/`-vv`/`--quiet` and the `HASSHA_LOG` filter.
+//!
+//! Hooks run inside the agent, where a failing command shows up as a hook
+//! error. By default `hassha hook` therefore exits 0 and writes its errors
+//! to the log instead; `hassha log` shows the most recent lines. The log is
+//! rotated to `hassha.log.1` when it grows past [`MAX_LOG_SIZE`].
+
+use anyhow::{Context, Result};
+use chrono::{Local, SecondsFormat};
+use log::kv::{self, Key, Value, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::Deserialize;
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Size at which the log is rotated
+const MAX_LOG_SIZE: u64 = 512 * 1024;
+
+/// Level when neither flags nor `HASSHA_LOG` set one
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Warn;
+
+/// A `HASSHA_LOG` filter: comma-separated directives, each a level (e.g.,
+/// `debug`) or a module and a level (e.g., `cache=trace`)
+#[derive(Debug, Default, PartialEq)]
+struct LogFilter {
+    level: Option<LevelFilter>,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    /// Parse a filter, ignoring invalid directives
+    fn parse(spec: &str) -> Self {
+        let mut filter = LogFilter::default();
+        for directive in spec.split(',').map(str::trim) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    if let Ok(level) = level.trim().parse() {
+                        filter.modules.push((module.trim().to_string(), level));
+                    }
+                }
+                None => {
+                    if let Ok(level) = directive.parse() {
+                        filter.level = Some(level);
+                    }
+                }
+            }
+        }
+        filter
+    }
+
+    /// The level for a log target, given the overall `level`. Targets are
+    /// matched without the `hassha::` prefix; other crates log warnings only,
+    /// unless named in the filter.
+    fn level_for(&self, target: &str, level: LevelFilter) -> LevelFilter {
+        let module = target.strip_prefix("hassha::").unwrap_or(target);
+        let directive = self.modules.iter().rev().find(|(name, _)| {
+            module == name
+                || module
+                    .strip_prefix(name.as_str())
+                    .is_some_and(|rest| rest.starts_with("::"))
+        });
+
+        match directive {
+            Some((_, level)) => *level,
+            None if target == "hassha" || target.starts_with("hassha::") => level,
+            None => level.min(LevelFilter::Warn),
+        }
+    }
+}
+
+/// Formats a record's key-values as ` key=value`
+struct KeyValues<'a>(&'a mut String);
+
+impl<'kvs> VisitSource<'kvs> for KeyValues<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = value.to_string();
+        if value.is_empty() || value.contains(char::is_whitespace) {
+            let _ = write!(self.0, " {}={:?}", key, value);
+        } else {
+            let _ = write!(self.0, " {}={}", key, value);
+        }
+        Ok(())
+    }
+}
+
+/// Writes log events to stderr, or to the log file
+struct Logger {
+    filter: LogFilter,
+    level: LevelFilter,
+    file: Option<PathBuf>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.level_for(metadata.target(), self.level)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let target = record.target();
+        let mut message = format!(
+            "{}: {}",
+            target.strip_prefix("hassha::").unwrap_or(target),
+            record.args()
+        );
+        let _ = record.key_values().visit(&mut KeyValues(&mut message));
+
+        match &self.file {
+            Some(path) => {
+                let line = format_line(record.level().as_str(), &message);
+                let _ = append_line(path, &line, MAX_LOG_SIZE);
+            }
+            None => eprintln!("[{}] {}", record.level(), message),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Set up logging for this process: the level from `verbose` (`-v` count)
+/// or `quiet`, falling back to `HASSHA_LOG`, and writing to the log file
+/// instead of stderr with `to_file`
+pub fn init(verbose: u8, quiet: bool, to_file: bool) {
+    let flags = match (quiet, verbose) {
+        (true, _) => Some(LevelFilter::Off),
+        (false, 0) => None,
+        (false, 1) => Some(LevelFilter::Info),
+        (false, 2) => Some(LevelFilter::Debug),
+        (false, _) => Some(LevelFilter::Trace),
+    };
+    let filter = std::env::var("HASSHA_LOG")
+        .map(|spec| LogFilter::parse(&spec))
+        .unwrap_or_default();
+    let level = flags.or(filter.level).unwrap_or(DEFAULT_LEVEL);
+    let max_level = filter
+        .modules
+        .iter()
+        .map(|(_, level)| *level)
+        .fold(level, Ord::max);
+
+    let logger = Logger {
+        filter,
+        level,
+        file: to_file.then(|| log_file_path().ok()).flatten(),
+    };
+    if log::set_logger(Box::leak(Box::new(logger))).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// What `hassha hook` does when handling an event fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorPolicy {
+    /// Write the error to the log and exit 0
+    #[default]
+    Log,
+    /// Print the error and exit 1
+    Strict,
+}
+
+impl ErrorPolicy {
+    /// The policy in effect: `--strict`, then `HASSHA_ON_ERROR`, then
+    /// `on_error` in the user config
+    pub fn current(strict: bool) -> Self {
+        if strict {
+            return ErrorPolicy::Strict;
+        }
+
+        if let Ok(value) = std::env::var("HASSHA_ON_ERROR") {
+            match value.as_str() {
+                "strict" => return ErrorPolicy::Strict,
+                "log" => return ErrorPolicy::Log,
+                _ => log::warn!("Ignoring HASSHA_ON_ERROR={:?}: use log or strict", value),
+            }
+        }
+
+        match crate::config::load_user_config() {
+            Ok(config) => config
+                .and_then(|config| config.on_error)
+                .unwrap_or_default(),
+            Err(e) => {
+                log::warn!("Ignoring on_error, the user config failed to load: {:#}", e);
+                ErrorPolicy::default()
+            }
+        }
+    }
+}
+
+/// Get the path to the log file (`~/.hassha/hassha.log`)
+pub fn log_file_path() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    Ok(home.join(".hassha").join("hassha.log"))
+}
+
+/// The previous log, kept after rotation
+fn rotated_path(path: &Path) -> PathBuf {
+    path.with_extension("log.1")
+}
+
+/// Append an error to the log
+pub fn log_error(message: &str) -> Result<()> {
+    let path = log_file_path()?;
+    append_line(&path, &format_line("ERROR", message), MAX_LOG_SIZE)
+}
+
+/// Format a log line. Multi-line messages (e.g., TOML parse errors) are
+/// joined into one line.
+fn format_line(level: &str, message: &str) -> String {
+    let message: Vec<&str> = message
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    format!(
+        "{} {} {}",
+        Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+        level,
+        message.join(" ")
+    )
+}
+
+/// Append a line to the log at `path`, first rotating it if it has grown
+/// to `max_size`. Holds the log lock so that concurrent hooks rotate once
+/// and never append to a log being rotated away.
+fn append_line(path: &Path, line: &str, max_size: u64) -> Result<()> {
+    let _lock = crate::state::lock(&path.with_extension("lock"), true)?;
+
+    if fs::metadata(path).is_ok_and(|meta| meta.len() >= max_size) {
+        fs::rename(path, rotated_path(path))
+            .with_context(|| format!("Failed to rotate log: {}", path.display()))?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open log: {}", path.display()))?;
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
+
+/// The last `limit` error lines of the log (all with `None`), oldest first,
+/// including the rotated log. With `all`, lines of every level are included.
+pub fn read_log(limit: Option<usize>, all: bool) -> Result<Vec<String>> {
+    let mut lines = read_log_at(&log_file_path()?, None)?;
+    if !all {
+        lines.retain(|line| is_error_line(line));
+    }
+    if let Some(limit) = limit {
+        lines.drain(..lines.len().saturating_sub(limit));
+    }
+    Ok(lines)
+}
+
+/// Whether a log line (`<timestamp> <LEVEL> <message>`) is an error
+fn is_error_line(line: &str) -> bool {
+    line.split(' ').nth(1) == Some("ERROR")
+}
+
+fn read_log_at(path: &Path, limit: Option<usize>) -> Result<Vec<String>> {
+    let _lock = crate::state::lock(&path.with_extension("lock"), false)?;
+    let mut lines = Vec::new();
+    for path in [rotated_path(path), path.to_path_buf()] {
+        match fs::read_to_string(&path) {
+            Ok(content) => lines.extend(content.lines().map(str::to_string)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read log: {}", path.display()));
+            }
+        }
+    }
+
+    if let Some(limit) = limit {
+        lines.drain(..lines.len().saturating_sub(limit));
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("hassha-log-test-{}", std::process::id()));
+        let path = dir.join("hassha.log");
+        let _ = fs::remove_dir_all(&dir);
+
+        for i in 0..5 {
+            append_line(&path, &format!("line {}", i), 16).unwrap();
+        }
+
+        // Each 7-byte line fills the log past 16 bytes every third line
+        assert_eq!(fs::read_to_string(&path).unwrap(), "line 3\nline 4\n");
+        assert_eq!(
+            read_log_at(&path, None).unwrap(),
+            ["line 0", "line 1", "line 2", "line 3", "line 4"]
+        );
+        assert_eq!(read_log_at(&path, Some(2)).unwrap(), ["line 3", "line 4"]);
+
+        assert!(is_error_line(&format_line("ERROR", "hook Stop: failed")));
+        assert!(!is_error_line(&format_line("WARN", "ERROR in the message")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_log_filter() {
+        let filter = LogFilter::parse("info, cache=trace,player=bogus,reqwest=debug");
+        assert_eq!(filter.level, Some(LevelFilter::Info));
+        assert_eq!(filter.modules.len(), 2);
+
+        let level = LevelFilter::Info;
+        assert_eq!(filter.level_for("hassha::hook", level), LevelFilter::Info);
+        assert_eq!(filter.level_for("hassha::cache", level), LevelFilter::Trace);
+        assert_eq!(filter.level_for("hassha::cachet", level), LevelFilter::Info);
+        assert_eq!(
+            filter.level_for("reqwest::connect", level),
+            LevelFilter::Debug
+        );
+        assert_eq!(filter.level_for("symphonia_core", level), LevelFilter::Warn);
+
+        assert_eq!(LogFilter::parse(""), LogFilter::default());
+    }
+
+    #[test]
+    fn test_format_line() {
+        let line = format_line("ERROR", "Failed to parse\n  |\n1 | x =\n");
+        let (_, rest) = line.split_once(' ').unwrap();
+        assert_eq!(rest, "ERROR Failed to parse | 1 | x =");
+    }
+}
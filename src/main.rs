@@ -3,19 +3,35 @@
 //! Play JR East departure melodies or custom sounds on various events.
 //! Configure different melodies per project using `.hassha/config.toml`.
 
+mod audio;
 mod cache;
+mod catalog;
 mod cli;
 mod config;
+mod embedded;
+mod event;
 mod history;
 mod hook;
 mod install;
+mod listing;
+mod logging;
 mod melodies;
+mod pack;
+mod playback;
 mod player;
+mod probe;
+mod replay;
+mod selector;
+mod state;
+mod stats;
+mod synth;
+mod template;
+mod turns;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
 
-use cli::{CacheCommands, Cli, Commands, HistoryCommands};
+use cli::{CacheCommands, Cli, Commands, HistoryCommands, PackCommands};
 
 fn main() {
     if let Err(e) = run() {
@@ -27,46 +43,144 @@ fn main() {
 fn run() -> Result<()> {
     let cli = Cli::parse();
 
+    // Hooks log to ~/.hassha/hassha.log, since their stderr goes to the agent
+    let to_file = matches!(cli.command, Commands::Hook { dry_run: false, .. });
+    logging::init(cli.verbose, cli.quiet, to_file);
+
     match cli.command {
-        Commands::Hook { event } => {
-            hook::handle_hook(&event)?;
+        Commands::Hook {
+            event,
+            dry_run,
+            strict,
+        } => {
+            let result = hook::handle_hook(&event::HookEvent::parse(&event), dry_run);
+            if let Err(e) = result {
+                // Errors in hooks surface in the agent UI; log them instead
+                if dry_run || logging::ErrorPolicy::current(strict) == logging::ErrorPolicy::Strict
+                {
+                    return Err(e);
+                }
+                if logging::log_error(&format!("hook {}: {:#}", event, e)).is_err() {
+                    eprintln!("hassha: {:#}", e);
+                }
+            }
+        }
+
+        Commands::Explain {
+            event,
+            cwd,
+            payload,
+        } => {
+            let event = event::HookEvent::parse(&event);
+            let input = event::HookInput::build(&event, &project_dir(&cwd)?, &payload, false)?;
+            hook::explain(&event, &input)?;
+        }
+
+        Commands::Simulate {
+            event,
+            cwd,
+            payload,
+            null_audio,
+            no_record,
+            print,
+        } => {
+            let event = event::HookEvent::parse(&event);
+            let cwd = project_dir(&cwd)?;
+            if print {
+                let json = event::build_payload(&event, &cwd, &payload, true);
+                println!("{}", serde_json::to_string_pretty(&json)?);
+                return Ok(());
+            }
+
+            if null_audio {
+                player::use_null_backend();
+            }
+            let input = event::HookInput::build(&event, &cwd, &payload, true)?;
+            let run = hook::run_hook(&event, &input, !no_record)?;
+
+            print!("{}: {}", event, run.entry.outcome);
+            if !run.entry.melody.is_empty() {
+                print!(" ({})", run.entry.melody);
+            }
+            println!();
+            if let Some(message) = run.message {
+                println!("Message: {}", message);
+            }
         }
 
-        Commands::Play { melody, volume } => {
-            let path = cache::resolve_melody_path(&melody)?;
-            player::play_audio(&path, volume)?;
-            println!("Played: {}", melody);
+        Commands::Play {
+            melody,
+            volume,
+            gap,
+        } => {
+            // `-v` used to set the volume, as in `hassha play JY-Tokyo -v 0.5`
+            if cli.verbose > 0
+                && let Some(value) = melody.iter().find(|m| m.parse::<f32>().is_ok())
+            {
+                anyhow::bail!(
+                    "-v now sets log verbosity; use --volume {} to set the volume",
+                    value
+                );
+            }
+
+            let source = match melody.as_slice() {
+                [single] => config::MelodySource::Single(single.clone()),
+                _ => config::MelodySource::Sequence {
+                    sequence: melody,
+                    gap,
+                },
+            };
+
+            let config = load_current_config()?;
+            let resolved = config.resolve_melody(&source, volume)?;
+            let registry = melodies::MelodyRegistry::load(Some(&config))?;
+            let audio = cache::resolve_audio(&resolved.source, &registry, resolved.selection)?;
+            player::play_audio(&audio.path, resolved.volume, resolved.end)?;
+
+            let label = audio.label();
+            if label == source.to_string() {
+                println!("Played: {}", label);
+            } else {
+                println!("Played: {} ({})", source, label);
+            }
         }
 
-        Commands::List => {
+        Commands::List {
+            line,
+            station,
+            melody,
+            search,
+            format,
+        } => {
+            let registry = load_registry()?;
+            let filter = listing::MelodyFilter {
+                line,
+                station,
+                melody,
+                search,
+            };
+            let melodies: Vec<_> = registry
+                .all()
+                .iter()
+                .copied()
+                .filter(|m| filter.matches(m))
+                .collect();
+
+            if format != listing::ListFormat::Table {
+                print!("{}", listing::format_melodies(&melodies, format));
+                return Ok(());
+            }
+
+            if melodies.is_empty() {
+                println!("No melodies match the given filters");
+                return Ok(());
+            }
+
             println!("Available melodies (JR East Lines):\n");
             println!(
                 "Lines: JY=Yamanote, JK=Keihin-Tohoku, JB=Sobu, JA=Saikyo, JU=Ueno-Tokyo, NEX=Narita Express\n"
             );
-            println!(
-                "{:<22} {:<15} {:<18} {:<10} {}",
-                "ID", "Line", "Station", "Japanese", "Melody"
-            );
-            println!("{}", "-".repeat(85));
-
-            let mut current_line = "";
-            for melody in melodies::MELODIES {
-                // Add separator between lines
-                if melody.line != current_line {
-                    if !current_line.is_empty() {
-                        println!();
-                    }
-                    current_line = melody.line;
-                }
-                println!(
-                    "{:<22} {:<15} {:<18} {:<10} {}",
-                    melody.id,
-                    melody.line_name,
-                    melody.station,
-                    melody.station_jp,
-                    melody.melody_name
-                );
-            }
+            print!("{}", listing::format_table(&melodies));
 
             println!("\nUsage in .hassha/config.toml:");
             println!("  [hooks.Stop]");
@@ -75,22 +189,135 @@ fn run() -> Result<()> {
             println!("  # or: melody = \"NEX-Shinjuku\"");
         }
 
-        Commands::History { command } => match command {
+        Commands::Info { melody } => {
+            let registry = load_registry()?;
+            let info = registry
+                .get(&melody)
+                .ok_or_else(|| anyhow::anyhow!(cache::unknown_id_message(&melody, &registry)))?;
+            print!("{}", listing::format_info(info, &registry)?);
+        }
+
+        Commands::Check => {
+            let cwd = std::env::current_dir()?;
+            let path = config::find_config_file(&cwd)
+                .or_else(|| config::user_config_path().ok().filter(|p| p.exists()));
+            let Some(path) = path else {
+                println!("No config file found");
+                return Ok(());
+            };
+
+            let config = load_current_config()?;
+            let registry = melodies::MelodyRegistry::load(Some(&config))?;
+            let mut problems = config.validate(&registry);
+
+            // `on_error` is only read from the user config
+            if config.on_error.is_some() && path != config::user_config_path()? {
+                problems.push("on_error is only read from the user config".to_string());
+            }
+
+            if problems.is_empty() {
+                println!("{}: OK", path.display());
+                return Ok(());
+            }
+
+            for problem in &problems {
+                println!("  ✗ {}", problem);
+            }
+            anyhow::bail!("{} problem(s) in {}", problems.len(), path.display());
+        }
+
+        Commands::History {
+            command,
+            event,
+            project,
+            since,
+            melody,
+            outcome,
+            session,
+            follow,
+            limit,
+            format,
+        } => match command {
             Some(HistoryCommands::Clear) => {
-                let path = history::history_file_path()?;
-                if path.exists() {
-                    std::fs::remove_file(&path)?;
+                if history::clear_history()? {
                     println!("History cleared");
                 } else {
                     println!("No history to clear");
                 }
             }
+            Some(HistoryCommands::Compact) => {
+                let stats = history::compact_history()?;
+                println!(
+                    "Compacted history: kept {} of {} entries",
+                    stats.after, stats.before
+                );
+            }
+            Some(HistoryCommands::Sessions) => {
+                let hist = history::load_history(&history::HistoryFilter::default(), None)?;
+                let sessions = history::summarize_sessions(&hist.entries);
+                print!("{}", history::format_sessions(&sessions));
+            }
             None => {
-                let hist = history::load_history()?;
-                print!("{}", history::format_history(&hist));
+                let filter = history::HistoryFilter {
+                    event,
+                    melody,
+                    outcome,
+                    session,
+                    ..history_filter(project, since)?
+                };
+                let limit = (limit > 0).then_some(limit);
+
+                if follow {
+                    return history::follow_history(&filter, limit, history::use_color());
+                }
+
+                let hist = history::load_history(&filter, limit)?;
+                print!("{}", history::format_history_as(&hist, format));
             }
         },
 
+        Commands::Replay {
+            session,
+            speed,
+            volume,
+        } => {
+            replay::replay_session(&session, speed, volume)?;
+        }
+
+        Commands::Stats {
+            since,
+            project,
+            top,
+            json,
+        } => {
+            let filter = history_filter(project, since)?;
+            let hist = history::load_history(&filter, None)?;
+            let stats = stats::Stats::from_entries(&hist.entries, top);
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                print!("{}", stats::format_stats(&stats));
+            }
+        }
+
+        Commands::Log { limit, all } => {
+            let lines = logging::read_log((limit > 0).then_some(limit), all)?;
+            if lines.is_empty() {
+                println!(
+                    "{}",
+                    if all {
+                        "Nothing logged"
+                    } else {
+                        "No errors logged"
+                    }
+                );
+            }
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+
         Commands::Cache { command } => match command {
             CacheCommands::Info => {
                 let stats = cache::cache_info()?;
@@ -100,6 +327,7 @@ fn run() -> Result<()> {
                     "Total size:     {:.2} MB",
                     stats.total_size as f64 / 1_000_000.0
                 );
+                println!("Embedded files: {}", stats.embedded_count);
             }
 
             CacheCommands::Clear => {
@@ -107,27 +335,113 @@ fn run() -> Result<()> {
                 println!("Cleared {} cached files", cleared);
             }
 
-            CacheCommands::Prefetch => {
-                println!("Downloading all predefined melodies...\n");
-                let results = cache::prefetch_all()?;
+            CacheCommands::Prefetch {
+                line,
+                configured,
+                ids,
+                jobs,
+            } => {
+                let config = load_current_config()?;
+                let registry = melodies::MelodyRegistry::load(Some(&config))?;
+
+                let targets = cache::prefetch_targets(
+                    &registry,
+                    line.as_deref(),
+                    &ids,
+                    configured.then_some(&config),
+                )?;
+                if targets.is_empty() {
+                    println!("Nothing to prefetch");
+                    return Ok(());
+                }
+
+                println!("Downloading {} melodies...\n", targets.len());
+                let total = targets.len();
+                let results = cache::prefetch(&targets, &registry, jobs, |done, source, result| {
+                    let mark = if result.is_ok() { "✓" } else { "✗" };
+                    println!(
+                        "  [{:>width$}/{}] {} {}",
+                        done,
+                        total,
+                        mark,
+                        source,
+                        width = total.to_string().len()
+                    );
+                });
 
-                let mut success = 0;
-                let mut failed = 0;
+                let failures: Vec<_> = results
+                    .iter()
+                    .filter_map(|(id, result)| result.as_ref().err().map(|e| (id, e)))
+                    .collect();
 
-                for (id, result) in results {
-                    match result {
-                        Ok(path) => {
-                            println!("  ✓ {} -> {}", id, path.display());
-                            success += 1;
-                        }
-                        Err(e) => {
-                            println!("  ✗ {} - {}", id, e);
-                            failed += 1;
-                        }
+                println!(
+                    "\nDownloaded: {}, Failed: {}",
+                    total - failures.len(),
+                    failures.len()
+                );
+
+                if !failures.is_empty() {
+                    println!("\nFailures:");
+                    for (id, e) in &failures {
+                        println!("  ✗ {} - {:#}", id, e);
                     }
+                    anyhow::bail!(
+                        "{} of {} melodies failed to download",
+                        failures.len(),
+                        total
+                    );
                 }
+            }
 
-                println!("\nDownloaded: {}, Failed: {}", success, failed);
+            CacheCommands::Export { output } => {
+                let exported = cache::cache_export(&output)?;
+                println!("Exported {} cached files to {}", exported, output.display());
+            }
+
+            CacheCommands::Import { input } => {
+                let stats = cache::cache_import(&input)?;
+                println!(
+                    "Imported {} files ({} already cached)",
+                    stats.imported, stats.skipped
+                );
+            }
+        },
+
+        Commands::Pack { command } => match command {
+            PackCommands::Add { source } => {
+                let manifest = pack::add_pack(&source)?;
+                println!(
+                    "Installed pack {} ({} melodies)",
+                    manifest.name,
+                    manifest.melodies.len()
+                );
+                println!("Use as: melody = \"{}:<ID>\"", manifest.name);
+            }
+
+            PackCommands::List => {
+                let packs = pack::installed_packs()?;
+                if packs.is_empty() {
+                    println!("No packs installed");
+                    return Ok(());
+                }
+
+                for (manifest, _) in packs {
+                    println!(
+                        "{:<20} {:<10} {:>3} melodies  {}",
+                        manifest.name,
+                        manifest.version.as_deref().unwrap_or("-"),
+                        manifest.melodies.len(),
+                        manifest.description.as_deref().unwrap_or("")
+                    );
+                    for melody in &manifest.melodies {
+                        println!("  {}:{}", manifest.name, melody.id);
+                    }
+                }
+            }
+
+            PackCommands::Remove { name } => {
+                pack::remove_pack(&name)?;
+                println!("Removed pack {}", name);
             }
         },
 
@@ -152,3 +466,46 @@ fn run() -> Result<()> {
 
     Ok(())
 }
+
+/// Resolve a `--cwd` directory to an absolute path
+fn project_dir(dir: &std::path::Path) -> Result<std::path::PathBuf> {
+    dir.canonicalize()
+        .with_context(|| format!("Directory not found: {}", dir.display()))
+}
+
+/// Build a history filter from the shared `--project` and `--since` options
+fn history_filter(
+    project: Option<std::path::PathBuf>,
+    since: Option<String>,
+) -> Result<history::HistoryFilter> {
+    let project = project
+        .map(|dir| {
+            dir.canonicalize()
+                .with_context(|| format!("Project directory not found: {}", dir.display()))
+        })
+        .transpose()?;
+    let since = since
+        .map(|since| history::parse_since(&since, chrono::Utc::now()))
+        .transpose()?;
+
+    Ok(history::HistoryFilter {
+        project,
+        since,
+        ..Default::default()
+    })
+}
+
+/// Load the config for the current directory, falling back to the user config
+fn load_current_config() -> Result<config::HasshaConfig> {
+    let cwd = std::env::current_dir()?;
+    match config::load_config(&cwd)? {
+        Some(config) => Ok(config),
+        None => Ok(config::load_user_config()?.unwrap_or_default()),
+    }
+}
+
+/// Load the melody registry, including catalogs from the current project's config
+fn load_registry() -> Result<melodies::MelodyRegistry> {
+    let config = load_current_config()?;
+    melodies::MelodyRegistry::load(Some(&config))
+}
@@ -10,7 +10,16 @@
 //! - JU: Ueno-Tokyo Line (上野東京ライン)
 //! - NEX: Narita Express (成田エクスプレス)
 
+use anyhow::Result;
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use crate::catalog::{self, Catalog, CatalogMelody};
+use crate::config::HasshaConfig;
+use crate::pack::{self, PackManifest};
+use crate::probe;
 
 /// Information about a predefined melody
 #[derive(Debug, Clone)]
@@ -27,14 +36,123 @@ pub struct MelodyInfo {
     pub station_jp: &'static str,
     /// The melody name
     pub melody_name: &'static str,
-    /// The audio file name (without base URL)
-    pub filename: &'static str,
+    /// Where the audio file is
+    pub location: AudioLocation,
+    /// The audio duration in milliseconds, if declared. Otherwise it is
+    /// probed from the cached or embedded audio file.
+    pub duration_ms: Option<u32>,
+    /// The original composer / arrangement credit, if known
+    pub credit: Option<&'static str>,
+    /// Descriptive tags (e.g., "calm", "upbeat"). "short" and "long" are
+    /// also derived from the duration.
+    pub tags: &'static [&'static str],
+}
+
+/// Where a melody's audio file is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioLocation {
+    /// A file name on yamanot.es
+    Yamanotes(&'static str),
+    /// A full URL
+    Url(&'static str),
+    /// A local file
+    File(&'static Path),
+}
+
+impl std::fmt::Display for AudioLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioLocation::Yamanotes(filename) => write!(f, "{}{}", YAMANOTES_BASE_URL, filename),
+            AudioLocation::Url(url) => write!(f, "{}", url),
+            AudioLocation::File(path) => write!(f, "{}", path.display()),
+        }
+    }
 }
 
+/// Melodies up to this long are tagged "short"
+pub const SHORT_MAX: Duration = Duration::from_secs(6);
+
+/// Melodies at least this long are tagged "long"
+pub const LONG_MIN: Duration = Duration::from_secs(12);
+
 impl MelodyInfo {
-    /// Get the full URL for downloading this melody
-    pub fn url(&self) -> String {
-        format!("{}{}", YAMANOTES_BASE_URL, self.filename)
+    /// Get the full URL for downloading this melody, unless it is a local file
+    pub fn url(&self) -> Option<String> {
+        match self.location {
+            AudioLocation::File(_) => None,
+            location => Some(location.to_string()),
+        }
+    }
+
+    /// Get the local audio file for melodies backed by a file on disk
+    pub fn local_path(&self) -> Option<&'static Path> {
+        match self.location {
+            AudioLocation::File(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Get the audio embedded at build time, for yamanot.es melodies
+    pub fn embedded(&self) -> Option<&'static [u8]> {
+        match self.location {
+            AudioLocation::Yamanotes(filename) => crate::embedded::get(filename),
+            _ => None,
+        }
+    }
+
+    /// Get the audio duration: declared, or probed from the local, embedded
+    /// or cached audio file. `None` if unknown (e.g., not downloaded yet).
+    /// Each melody is probed at most once per process.
+    pub fn duration(&self) -> Option<Duration> {
+        static PROBED: LazyLock<Mutex<HashMap<&'static str, Option<Duration>>>> =
+            LazyLock::new(Default::default);
+
+        if let Some(ms) = self.duration_ms {
+            return Some(Duration::from_millis(ms as u64));
+        }
+
+        let mut probed = PROBED.lock().unwrap_or_else(|e| e.into_inner());
+        *probed
+            .entry(self.id)
+            .or_insert_with(|| self.probe_duration())
+    }
+
+    fn probe_duration(&self) -> Option<Duration> {
+        if let Some(path) = self.local_path() {
+            return probe::audio_duration(path);
+        }
+
+        if let Some(bytes) = self.embedded() {
+            return probe::bytes_duration(bytes);
+        }
+
+        let path = crate::cache::get_cached(&self.url()?).ok().flatten()?;
+        probe::audio_duration(&path)
+    }
+
+    /// Get all tags, including "short" / "long" derived from the duration
+    pub fn all_tags(&self) -> Vec<&'static str> {
+        let mut tags = self.tags.to_vec();
+        match self.duration() {
+            Some(d) if d <= SHORT_MAX => tags.push("short"),
+            Some(d) if d >= LONG_MIN => tags.push("long"),
+            _ => {}
+        }
+        tags
+    }
+
+    /// Check whether this melody has a tag (case-insensitive). Only the
+    /// derived "short" / "long" tags need the duration.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        if self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            return true;
+        }
+
+        match tag.to_ascii_lowercase().as_str() {
+            "short" => self.duration().is_some_and(|d| d <= SHORT_MAX),
+            "long" => self.duration().is_some_and(|d| d >= LONG_MIN),
+            _ => false,
+        }
     }
 }
 
@@ -53,7 +171,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Tokyo",
         station_jp: "東京",
         melody_name: "SH-3",
-        filename: "sh3.mp3",
+        location: AudioLocation::Yamanotes("sh3.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JY-Kanda",
@@ -62,7 +183,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Kanda",
         station_jp: "神田",
         melody_name: "Seseragi",
-        filename: "seseragi.mp3",
+        location: AudioLocation::Yamanotes("seseragi.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Akihabara",
@@ -71,7 +195,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Akihabara",
         station_jp: "秋葉原",
         melody_name: "Ogawa V1",
-        filename: "ogawav1.mp3",
+        location: AudioLocation::Yamanotes("ogawav1.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Okachimachi",
@@ -80,7 +207,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Okachimachi",
         station_jp: "御徒町",
         melody_name: "Haru Tremolo",
-        filename: "harutrem.mp3",
+        location: AudioLocation::Yamanotes("harutrem.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Ueno",
@@ -89,7 +219,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Ueno",
         station_jp: "上野",
         melody_name: "Bell B",
-        filename: "bellb.mp3",
+        location: AudioLocation::Yamanotes("bellb.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm", "bell"],
     },
     MelodyInfo {
         id: "JY-Uguisudani",
@@ -98,7 +231,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Uguisudani",
         station_jp: "鶯谷",
         melody_name: "Haru Tremolo",
-        filename: "harutrem.mp3",
+        location: AudioLocation::Yamanotes("harutrem.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Nippori",
@@ -107,7 +243,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Nippori",
         station_jp: "日暮里",
         melody_name: "Haru Tremolo",
-        filename: "harutrem.mp3",
+        location: AudioLocation::Yamanotes("harutrem.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-NishiNippori",
@@ -116,7 +255,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Nishi-Nippori",
         station_jp: "西日暮里",
         melody_name: "Haru Tremolo",
-        filename: "harutrem.mp3",
+        location: AudioLocation::Yamanotes("harutrem.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Tabata",
@@ -125,7 +267,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Tabata",
         station_jp: "田端",
         melody_name: "Haru Tremolo",
-        filename: "harutrem.mp3",
+        location: AudioLocation::Yamanotes("harutrem.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Komagome",
@@ -134,7 +279,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Komagome",
         station_jp: "駒込",
         melody_name: "Sakura B",
-        filename: "sakurab.mp3",
+        location: AudioLocation::Yamanotes("sakurab.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Sugamo",
@@ -143,7 +291,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Sugamo",
         station_jp: "巣鴨",
         melody_name: "Haru",
-        filename: "haru.mp3",
+        location: AudioLocation::Yamanotes("haru.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Otsuka",
@@ -152,7 +303,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Otsuka",
         station_jp: "大塚",
         melody_name: "Haru",
-        filename: "haru.mp3",
+        location: AudioLocation::Yamanotes("haru.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Ikebukuro",
@@ -161,7 +315,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Ikebukuro",
         station_jp: "池袋",
         melody_name: "Melody",
-        filename: "melody.mp3",
+        location: AudioLocation::Yamanotes("melody.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JY-Mejiro",
@@ -170,7 +327,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Mejiro",
         station_jp: "目白",
         melody_name: "Haru",
-        filename: "haru.mp3",
+        location: AudioLocation::Yamanotes("haru.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Takadanobaba",
@@ -179,7 +339,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Takadanobaba",
         station_jp: "高田馬場",
         melody_name: "Astro Boy",
-        filename: "astrob.mp3",
+        location: AudioLocation::Yamanotes("astrob.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat", "anime"],
     },
     MelodyInfo {
         id: "JY-ShinOkubo",
@@ -188,7 +351,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Shin-Okubo",
         station_jp: "新大久保",
         melody_name: "Bell B",
-        filename: "bellb.mp3",
+        location: AudioLocation::Yamanotes("bellb.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm", "bell"],
     },
     MelodyInfo {
         id: "JY-Shinjuku",
@@ -197,7 +363,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Shinjuku",
         station_jp: "新宿",
         melody_name: "Aratana",
-        filename: "aratana.mp3",
+        location: AudioLocation::Yamanotes("aratana.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JY-Yoyogi",
@@ -206,7 +375,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Yoyogi",
         station_jp: "代々木",
         melody_name: "Haru",
-        filename: "haru.mp3",
+        location: AudioLocation::Yamanotes("haru.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Harajuku",
@@ -215,7 +387,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Harajuku",
         station_jp: "原宿",
         melody_name: "Harajuku A",
-        filename: "harajukua.mp3",
+        location: AudioLocation::Yamanotes("harajukua.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JY-Shibuya",
@@ -224,7 +399,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Shibuya",
         station_jp: "渋谷",
         melody_name: "Hana no Horokobi",
-        filename: "hananohorokobi.mp3",
+        location: AudioLocation::Yamanotes("hananohorokobi.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Ebisu",
@@ -233,7 +411,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Ebisu",
         station_jp: "恵比寿",
         melody_name: "Third Man",
-        filename: "thirdman.mp3",
+        location: AudioLocation::Yamanotes("thirdman.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat", "film"],
     },
     MelodyInfo {
         id: "JY-Meguro",
@@ -242,7 +423,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Meguro",
         station_jp: "目黒",
         melody_name: "Water Crown",
-        filename: "watercrown.mp3",
+        location: AudioLocation::Yamanotes("watercrown.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Gotanda",
@@ -251,7 +435,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Gotanda",
         station_jp: "五反田",
         melody_name: "SH-23",
-        filename: "sh23.mp3",
+        location: AudioLocation::Yamanotes("sh23.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JY-Osaki",
@@ -260,7 +447,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Osaki",
         station_jp: "大崎",
         melody_name: "Umi no Eki",
-        filename: "uminoeki.mp3",
+        location: AudioLocation::Yamanotes("uminoeki.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Shinagawa",
@@ -269,7 +459,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Shinagawa",
         station_jp: "品川",
         melody_name: "Seseragi",
-        filename: "seseragi.mp3",
+        location: AudioLocation::Yamanotes("seseragi.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-TakanawaGateway",
@@ -278,7 +471,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Takanawa Gateway",
         station_jp: "高輪ゲートウェイ",
         melody_name: "Sweet Call",
-        filename: "sweetcall.mp3",
+        location: AudioLocation::Yamanotes("sweetcall.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JY-Tamachi",
@@ -287,7 +483,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Tamachi",
         station_jp: "田町",
         melody_name: "Seseragi",
-        filename: "seseragi.mp3",
+        location: AudioLocation::Yamanotes("seseragi.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Hamamatsucho",
@@ -296,7 +495,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Hamamatsucho",
         station_jp: "浜松町",
         melody_name: "Seseragi",
-        filename: "seseragi.mp3",
+        location: AudioLocation::Yamanotes("seseragi.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Shimbashi",
@@ -305,7 +507,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Shimbashi",
         station_jp: "新橋",
         melody_name: "Gota del Vient",
-        filename: "gotadelvient.mp3",
+        location: AudioLocation::Yamanotes("gotadelvient.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JY-Yurakucho",
@@ -314,7 +519,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Yurakucho",
         station_jp: "有楽町",
         melody_name: "SH-21",
-        filename: "sh21.mp3",
+        location: AudioLocation::Yamanotes("sh21.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     // ============================================
     // JK: Keihin-Tohoku Line (京浜東北線)
@@ -326,7 +534,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Shinagawa",
         station_jp: "品川",
         melody_name: "Chime",
-        filename: "chime.mp3",
+        location: AudioLocation::Yamanotes("chime.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm", "bell"],
     },
     MelodyInfo {
         id: "JK-TakanawaGateway",
@@ -335,7 +546,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Takanawa Gateway",
         station_jp: "高輪ゲートウェイ",
         melody_name: "Flower Shop",
-        filename: "flowershop.mp3",
+        location: AudioLocation::Yamanotes("flowershop.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JK-Tamachi",
@@ -344,7 +558,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Tamachi",
         station_jp: "田町",
         melody_name: "Spring Box",
-        filename: "springbox.mp3",
+        location: AudioLocation::Yamanotes("springbox.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JK-Hamamatsucho",
@@ -353,7 +570,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Hamamatsucho",
         station_jp: "浜松町",
         melody_name: "Spring Box",
-        filename: "springbox.mp3",
+        location: AudioLocation::Yamanotes("springbox.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JK-Shimbashi",
@@ -362,7 +582,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Shimbashi",
         station_jp: "新橋",
         melody_name: "SH-1",
-        filename: "sh1.mp3",
+        location: AudioLocation::Yamanotes("sh1.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JK-Yurakucho",
@@ -371,7 +594,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Yurakucho",
         station_jp: "有楽町",
         melody_name: "SH-5",
-        filename: "sh5.mp3",
+        location: AudioLocation::Yamanotes("sh5.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JK-Tokyo",
@@ -380,7 +606,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Tokyo",
         station_jp: "東京",
         melody_name: "SH-5",
-        filename: "sh5.mp3",
+        location: AudioLocation::Yamanotes("sh5.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JK-Kanda",
@@ -389,7 +618,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Kanda",
         station_jp: "神田",
         melody_name: "Haru New",
-        filename: "harunew.mp3",
+        location: AudioLocation::Yamanotes("harunew.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JK-Akihabara",
@@ -398,7 +630,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Akihabara",
         station_jp: "秋葉原",
         melody_name: "Beyond the Line",
-        filename: "beyondtheline.mp3",
+        location: AudioLocation::Yamanotes("beyondtheline.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JK-Okachimachi",
@@ -407,7 +642,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Okachimachi",
         station_jp: "御徒町",
         melody_name: "Haru New",
-        filename: "harunew.mp3",
+        location: AudioLocation::Yamanotes("harunew.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JK-Ueno",
@@ -416,7 +654,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Ueno",
         station_jp: "上野",
         melody_name: "Bell A",
-        filename: "bella.mp3",
+        location: AudioLocation::Yamanotes("bella.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm", "bell"],
     },
     MelodyInfo {
         id: "JK-Uguisudani",
@@ -425,7 +666,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Uguisudani",
         station_jp: "鶯谷",
         melody_name: "Haru New",
-        filename: "harunew.mp3",
+        location: AudioLocation::Yamanotes("harunew.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JK-Nippori",
@@ -434,7 +678,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Nippori",
         station_jp: "日暮里",
         melody_name: "Haru New",
-        filename: "harunew.mp3",
+        location: AudioLocation::Yamanotes("harunew.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JK-NishiNippori",
@@ -443,7 +690,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Nishi-Nippori",
         station_jp: "西日暮里",
         melody_name: "Haru New",
-        filename: "harunew.mp3",
+        location: AudioLocation::Yamanotes("harunew.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JK-Tabata",
@@ -452,7 +702,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Tabata",
         station_jp: "田端",
         melody_name: "Haru New",
-        filename: "harunew.mp3",
+        location: AudioLocation::Yamanotes("harunew.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     // ============================================
     // JB: Sobu Line (総武線)
@@ -464,7 +717,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Ichigaya",
         station_jp: "市ケ谷",
         melody_name: "Haru New",
-        filename: "harunew.mp3",
+        location: AudioLocation::Yamanotes("harunew.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JB-Iidabashi",
@@ -473,7 +729,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Iidabashi",
         station_jp: "飯田橋",
         melody_name: "SF-3",
-        filename: "sf3.mp3",
+        location: AudioLocation::Yamanotes("sf3.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JB-Suidobashi",
@@ -482,7 +741,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Suidobashi",
         station_jp: "水道橋",
         melody_name: "Fighting Spirit A",
-        filename: "fightingspirita.mp3",
+        location: AudioLocation::Yamanotes("fightingspirita.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JB-Ochanomizu",
@@ -491,7 +753,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Ochanomizu",
         station_jp: "御茶ノ水",
         melody_name: "SH-6",
-        filename: "sh6.mp3",
+        location: AudioLocation::Yamanotes("sh6.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JB-Akihabara",
@@ -500,7 +765,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Akihabara",
         station_jp: "秋葉原",
         melody_name: "SF-3",
-        filename: "sf3.mp3",
+        location: AudioLocation::Yamanotes("sf3.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     // ============================================
     // JA: Saikyo Line (埼京線)
@@ -512,7 +780,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Ikebukuro",
         station_jp: "池袋",
         melody_name: "Mellow Time",
-        filename: "mellowtime.mp3",
+        location: AudioLocation::Yamanotes("mellowtime.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JA-Shinjuku",
@@ -521,7 +792,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Shinjuku",
         station_jp: "新宿",
         melody_name: "Mellow Time",
-        filename: "mellowtime.mp3",
+        location: AudioLocation::Yamanotes("mellowtime.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "JA-Shibuya",
@@ -530,7 +804,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Shibuya",
         station_jp: "渋谷",
         melody_name: "SH-1",
-        filename: "sh1.mp3",
+        location: AudioLocation::Yamanotes("sh1.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JA-Ebisu",
@@ -539,7 +816,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Ebisu",
         station_jp: "恵比寿",
         melody_name: "Third Man",
-        filename: "thirdman.mp3",
+        location: AudioLocation::Yamanotes("thirdman.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat", "film"],
     },
     MelodyInfo {
         id: "JA-Osaki",
@@ -548,7 +828,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Osaki",
         station_jp: "大崎",
         melody_name: "Twinkling Skyline",
-        filename: "twinklingskyline.mp3",
+        location: AudioLocation::Yamanotes("twinklingskyline.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     // ============================================
     // JU: Ueno-Tokyo Line (上野東京ライン)
@@ -560,7 +843,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Shinagawa",
         station_jp: "品川",
         melody_name: "Railroad Song B",
-        filename: "railroadsongb.mp3",
+        location: AudioLocation::Yamanotes("railroadsongb.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JU-Shimbashi",
@@ -569,7 +855,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Shimbashi",
         station_jp: "新橋",
         melody_name: "Sunlight",
-        filename: "sunlight.mp3",
+        location: AudioLocation::Yamanotes("sunlight.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "JU-Tokyo",
@@ -578,7 +867,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Tokyo",
         station_jp: "東京",
         melody_name: "Bell A",
-        filename: "bella.mp3",
+        location: AudioLocation::Yamanotes("bella.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm", "bell"],
     },
     MelodyInfo {
         id: "JU-Ueno",
@@ -587,7 +879,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Ueno",
         station_jp: "上野",
         melody_name: "Beyond the Line",
-        filename: "beyondtheline.mp3",
+        location: AudioLocation::Yamanotes("beyondtheline.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     // ============================================
     // NEX: Narita Express (成田エクスプレス)
@@ -599,7 +894,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Ikebukuro",
         station_jp: "池袋",
         melody_name: "Haru",
-        filename: "haru.mp3",
+        location: AudioLocation::Yamanotes("haru.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "NEX-Shinjuku",
@@ -608,7 +906,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Shinjuku",
         station_jp: "新宿",
         melody_name: "Beautiful Hill",
-        filename: "beautifulhill.mp3",
+        location: AudioLocation::Yamanotes("beautifulhill.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
     MelodyInfo {
         id: "NEX-Shibuya",
@@ -617,7 +918,10 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Shibuya",
         station_jp: "渋谷",
         melody_name: "SH-1",
-        filename: "sh1.mp3",
+        location: AudioLocation::Yamanotes("sh1.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["upbeat"],
     },
     MelodyInfo {
         id: "NEX-Shinagawa",
@@ -626,26 +930,108 @@ pub const MELODIES: &[MelodyInfo] = &[
         station: "Shinagawa",
         station_jp: "品川",
         melody_name: "Seseragi",
-        filename: "seseragi.mp3",
+        location: AudioLocation::Yamanotes("seseragi.mp3"),
+        duration_ms: None,
+        credit: None,
+        tags: &["calm"],
     },
 ];
 
 /// Registry for looking up melodies by ID
 pub struct MelodyRegistry {
     melodies: HashMap<String, &'static MelodyInfo>,
+    all: Vec<&'static MelodyInfo>,
 }
 
 impl MelodyRegistry {
-    /// Create a new melody registry
+    /// Create a new melody registry with the built-in melodies
     pub fn new() -> Self {
-        let mut melodies = HashMap::new();
+        let mut registry = Self {
+            melodies: HashMap::new(),
+            all: Vec::new(),
+        };
         for melody in MELODIES {
-            // Add with exact ID
-            melodies.insert(melody.id.to_string(), melody);
-            // Add with lowercase ID for case-insensitive lookup
-            melodies.insert(melody.id.to_lowercase(), melody);
+            registry.insert(melody);
+        }
+        registry
+    }
+
+    /// Create a registry with the built-in melodies, user catalogs from
+    /// `~/.hassha/catalogs/`, the catalogs listed in a project config, and
+    /// installed packs from `~/.hassha/packs/`
+    pub fn load(config: Option<&HasshaConfig>) -> Result<Self> {
+        let mut registry = Self::new();
+
+        let mut paths = catalog::catalog_files_in(&catalog::user_catalog_dir()?)?;
+        if let Some(config) = config {
+            paths.extend(config.catalogs.iter().cloned());
         }
-        Self { melodies }
+
+        // A broken catalog or pack is skipped, so that it can't break hooks
+        for path in paths {
+            match catalog::load_catalog(&path) {
+                Ok(catalog) => registry.add_catalog(catalog, &path),
+                Err(e) => log::warn!("Skipping catalog: {:#}", e),
+            }
+        }
+
+        for (manifest, dir) in pack::installed_packs()? {
+            registry.add_pack(manifest, &dir);
+        }
+
+        Ok(registry)
+    }
+
+    /// Add all melodies from a catalog, skipping invalid entries and
+    /// duplicate IDs
+    pub fn add_catalog(&mut self, catalog: Catalog, path: &Path) {
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        let origin = format!("catalog {}", path.display());
+        self.add_entries(catalog.melodies, base_dir, None, &origin);
+    }
+
+    /// Add all melodies from an installed pack, namespaced as `{pack}:{id}`
+    pub fn add_pack(&mut self, manifest: PackManifest, dir: &Path) {
+        let origin = format!("pack {}", manifest.name);
+        self.add_entries(manifest.melodies, dir, Some(&manifest.name), &origin);
+    }
+
+    fn add_entries(
+        &mut self,
+        entries: Vec<CatalogMelody>,
+        base_dir: &Path,
+        namespace: Option<&str>,
+        origin: &str,
+    ) {
+        for entry in entries {
+            let info = match entry.into_info(base_dir, namespace) {
+                Ok(info) => info,
+                Err(e) => {
+                    log::warn!("Skipping invalid melody in {}: {:#}", origin, e);
+                    continue;
+                }
+            };
+
+            if let Some(existing) = self.get(info.id) {
+                log::warn!(
+                    "Skipping duplicate melody ID {} in {} (already defined as {})",
+                    info.id,
+                    origin,
+                    existing.id
+                );
+                continue;
+            }
+
+            self.insert(Box::leak(Box::new(info)));
+        }
+    }
+
+    fn insert(&mut self, melody: &'static MelodyInfo) {
+        // Add with exact ID
+        self.melodies.insert(melody.id.to_string(), melody);
+        // Add with lowercase ID for case-insensitive lookup
+        self.melodies.insert(melody.id.to_lowercase(), melody);
+        self.all.push(melody);
     }
 
     /// Look up a melody by ID (case-insensitive)
@@ -656,11 +1042,97 @@ impl MelodyRegistry {
             .copied()
     }
 
-    /// Get all melodies
-    #[allow(dead_code)]
-    pub fn all(&self) -> &'static [MelodyInfo] {
-        MELODIES
+    /// Get all melodies, built-in first, in catalog order
+    pub fn all(&self) -> &[&'static MelodyInfo] {
+        &self.all
+    }
+
+    /// Check whether a source looks like a melody ID (`XX-Name` or
+    /// `pack:XX-Name`) rather than a file path
+    pub fn looks_like_id(&self, source: &str) -> bool {
+        let id = source.rsplit_once(':').map_or(source, |(_, id)| id);
+        let Some((line, name)) = id.split_once('-') else {
+            return false;
+        };
+
+        let line_like = (2..=4).contains(&line.len())
+            && line.chars().all(|c| c.is_ascii_uppercase())
+            || self.all.iter().any(|m| m.line.eq_ignore_ascii_case(line));
+
+        line_like && !name.is_empty() && !name.contains(['/', '\\', '.'])
+    }
+
+    /// Suggest melodies close to an unknown ID, by edit distance against
+    /// the ID and the English and Japanese station names
+    pub fn suggest(&self, input: &str) -> Vec<&'static MelodyInfo> {
+        let input_id = normalize(input);
+        let (input_line, input_name) = match input.split_once('-') {
+            Some((line, name)) => (Some(line), normalize(name)),
+            None => (None, input_id.clone()),
+        };
+
+        let mut scored: Vec<(usize, bool, &'static MelodyInfo)> = self
+            .all
+            .iter()
+            .filter_map(|&melody| {
+                let distance = [
+                    edit_distance(&input_id, &normalize(melody.id)),
+                    edit_distance(&input_name, &normalize(melody.station)),
+                    edit_distance(&input_name, &normalize(melody.station_jp)),
+                ]
+                .into_iter()
+                .min()?;
+
+                let threshold = (input_name.chars().count() / 3).max(1);
+                let same_line = input_line.is_some_and(|l| melody.line.eq_ignore_ascii_case(l));
+                (distance <= threshold).then_some((distance, !same_line, melody))
+            })
+            .collect();
+
+        // Keep only the closest matches, preferring the input's line
+        scored.sort_by_key(|(distance, other_line, melody)| (*distance, *other_line, melody.id));
+        let Some(&(best_distance, best_other_line, _)) = scored.first() else {
+            return Vec::new();
+        };
+
+        scored
+            .into_iter()
+            .take_while(|(distance, other_line, _)| {
+                (*distance, *other_line) == (best_distance, best_other_line)
+            })
+            .take(3)
+            .map(|(_, _, m)| m)
+            .collect()
+    }
+}
+
+/// Normalize a name for fuzzy matching: lowercase, without separators
+fn normalize(s: &str) -> String {
+    s.chars()
+        .filter(|c| !matches!(c, '-' | ' ' | '_'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Levenshtein distance between two strings, by characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    if b.is_empty() {
+        return a.chars().count();
     }
+
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+
+    prev[b.len()]
 }
 
 impl Default for MelodyRegistry {
@@ -710,9 +1182,102 @@ mod tests {
         assert!(registry.get("XX-Unknown").is_none());
     }
 
+    #[test]
+    fn test_builtin_metadata() {
+        for melody in MELODIES {
+            assert!(
+                melody.has_tag("calm") != melody.has_tag("upbeat"),
+                "{} needs one mood tag",
+                melody.id
+            );
+        }
+    }
+
+    #[test]
+    fn test_catalog_merge() {
+        let mut registry = MelodyRegistry::new();
+        let catalog: Catalog = toml::from_str(
+            r#"
+[[melodies]]
+id = "JC-Tokyo"
+line = "JC"
+line_name = "Chuo"
+station = "Tokyo"
+filename = "jc-tokyo.mp3"
+"#,
+        )
+        .unwrap();
+
+        registry.add_catalog(catalog, Path::new("/catalogs/chuo.toml"));
+        assert_eq!(registry.get("jc-tokyo").unwrap().line_name, "Chuo");
+        assert_eq!(registry.all().len(), MELODIES.len() + 1);
+    }
+
+    #[test]
+    fn test_catalog_duplicate_id() {
+        let mut registry = MelodyRegistry::new();
+        let catalog: Catalog = toml::from_str(
+            r#"
+[[melodies]]
+id = "jy-tokyo"
+line = "jy"
+line_name = "Yamanote"
+station = "Tokyo"
+filename = "other.mp3"
+"#,
+        )
+        .unwrap();
+
+        // The duplicate is skipped, keeping the built-in melody
+        registry.add_catalog(catalog, Path::new("/catalogs/dup.toml"));
+        assert_eq!(registry.all().len(), MELODIES.len());
+        assert_eq!(
+            registry.get("JY-Tokyo").unwrap().location,
+            AudioLocation::Yamanotes("sh3.mp3")
+        );
+    }
+
+    #[test]
+    fn test_looks_like_id() {
+        let registry = MelodyRegistry::new();
+        assert!(registry.looks_like_id("JY-Shibya"));
+        assert!(registry.looks_like_id("jy-shibya"));
+        assert!(registry.looks_like_id("JY-渋谷"));
+        assert!(registry.looks_like_id("mypack:JR-Osaka"));
+        assert!(!registry.looks_like_id("my-sound.mp3"));
+        assert!(!registry.looks_like_id("/path/to/JY-Shibuya"));
+        assert!(!registry.looks_like_id("sound"));
+    }
+
+    #[test]
+    fn test_suggest() {
+        let registry = MelodyRegistry::new();
+
+        let suggestions = registry.suggest("JY-Shibya");
+        assert_eq!(suggestions[0].id, "JY-Shibuya");
+
+        // Same line is preferred over other lines with the same station
+        let suggestions = registry.suggest("NEX-Shibya");
+        assert_eq!(suggestions[0].id, "NEX-Shibuya");
+
+        // Japanese station names
+        let suggestions = registry.suggest("JY-渋谷");
+        assert_eq!(suggestions[0].id, "JY-Shibuya");
+
+        assert!(registry.suggest("XX-Completelyunknown").is_empty());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("shibuya", "shibuya"), 0);
+        assert_eq!(edit_distance("shibya", "shibuya"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("渋谷", "渋谷駅"), 1);
+    }
+
     #[test]
     fn test_melody_url() {
         let melody = &MELODIES[0]; // JY-Tokyo
-        assert_eq!(melody.url(), "https://yamanot.es/audio/sh3.mp3");
+        assert_eq!(melody.url().unwrap(), "https://yamanot.es/audio/sh3.mp3");
     }
 }
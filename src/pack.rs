@@ -0,0 +1,294 @@
+//! Installable melody packs for hassha.
+//!
+//! A pack is a `pack.toml` manifest plus audio files, either as a directory
+//! or a `.tar.zst` archive (local or URL). Packs are installed into
+//! `~/.hassha/packs/<name>/` and their melodies are available as
+//! `<name>:<ID>` (e.g., `mypack:JR-Osaka`).
+//!
+//! ```toml
+//! name = "mypack"
+//! description = "Osaka Loop Line"
+//!
+//! [[melodies]]
+//! id = "JR-Osaka"
+//! line = "JR"
+//! line_name = "Osaka Loop"
+//! station = "Osaka"
+//! station_jp = "大阪"
+//! file = "audio/osaka.mp3"
+//! sha256 = "…"
+//! ```
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::cache::{is_url, sha256_hex};
+use crate::catalog::CatalogMelody;
+use crate::selector::SELECTOR_PREFIXES;
+use crate::synth::SYNTH_PREFIX;
+
+/// The manifest file name inside a pack
+pub const MANIFEST_FILE: &str = "pack.toml";
+
+/// A pack manifest
+#[derive(Debug, Clone, Deserialize)]
+pub struct PackManifest {
+    /// The pack name, used as the melody ID namespace
+    pub name: String,
+
+    /// Optional pack version
+    #[serde(default)]
+    pub version: Option<String>,
+
+    /// Optional human-readable description
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// Melodies in this pack
+    #[serde(default)]
+    pub melodies: Vec<CatalogMelody>,
+}
+
+impl PackManifest {
+    /// Validate the pack name and that every melody has a safe relative
+    /// `file` and a `sha256` checksum
+    pub fn validate(&self) -> Result<()> {
+        if !is_valid_pack_name(&self.name) {
+            anyhow::bail!(
+                "Invalid pack name {:?}: use letters, digits, '-' and '_' only",
+                self.name
+            );
+        }
+
+        for melody in &self.melodies {
+            let file = melody
+                .file
+                .as_ref()
+                .with_context(|| format!("Melody {} has no `file`", melody.id))?;
+
+            if !is_safe_relative_path(file) {
+                anyhow::bail!(
+                    "Melody {} has an invalid file path: {}",
+                    melody.id,
+                    file.display()
+                );
+            }
+
+            if melody.sha256.is_none() {
+                anyhow::bail!("Melody {} has no `sha256` checksum", melody.id);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the pack installation directory (`~/.hassha/packs/`)
+pub fn packs_dir() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    Ok(home.join(".hassha").join("packs"))
+}
+
+/// Load a pack manifest from a pack directory
+pub fn load_manifest(dir: &Path) -> Result<PackManifest> {
+    let path = dir.join(MANIFEST_FILE);
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read pack manifest: {}", path.display()))?;
+
+    toml::from_str(&content)
+        .with_context(|| format!("Failed to parse pack manifest: {}", path.display()))
+}
+
+/// List installed packs with their directories, sorted by name. Packs
+/// whose manifest can't be loaded are skipped with a warning.
+pub fn installed_packs() -> Result<Vec<(PackManifest, PathBuf)>> {
+    let dir = packs_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut packs = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.is_dir() && path.join(MANIFEST_FILE).exists() {
+            match load_manifest(&path) {
+                Ok(manifest) => packs.push((manifest, path)),
+                Err(e) => log::warn!("Skipping pack: {:#}", e),
+            }
+        }
+    }
+
+    packs.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+    Ok(packs)
+}
+
+/// Install a pack from a directory, a `.tar.zst` archive, or a URL to one.
+///
+/// All checksums are verified before the pack is installed. An installed pack
+/// with the same name is replaced.
+pub fn add_pack(source: &str) -> Result<PackManifest> {
+    let packs = packs_dir()?;
+    fs::create_dir_all(&packs).context("Failed to create packs directory")?;
+
+    let source_path = Path::new(source);
+    if !is_url(source) && source_path.is_dir() {
+        return install_from_dir(source_path, &packs);
+    }
+
+    // Archives are unpacked into a staging directory first
+    let staging = packs.join(format!(".staging-{}", std::process::id()));
+    let result = unpack_archive(source, &staging).and_then(|_| install_from_dir(&staging, &packs));
+    let _ = fs::remove_dir_all(&staging);
+
+    result
+}
+
+/// Remove an installed pack by name
+pub fn remove_pack(name: &str) -> Result<()> {
+    if !is_valid_pack_name(name) {
+        anyhow::bail!("Invalid pack name: {}", name);
+    }
+
+    let dir = packs_dir()?.join(name);
+    if !dir.exists() {
+        anyhow::bail!("Pack not installed: {}", name);
+    }
+
+    fs::remove_dir_all(&dir)
+        .with_context(|| format!("Failed to remove pack directory: {}", dir.display()))
+}
+
+/// Unpack a `.tar.zst` archive from a local path or URL
+fn unpack_archive(source: &str, dest: &Path) -> Result<()> {
+    let bytes = if is_url(source) {
+        let response = reqwest::blocking::get(source)
+            .with_context(|| format!("Failed to download: {}", source))?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("Failed to download {}: HTTP {}", source, response.status());
+        }
+
+        response
+            .bytes()
+            .with_context(|| format!("Failed to read response body from: {}", source))?
+            .to_vec()
+    } else {
+        fs::read(source).with_context(|| format!("Failed to read pack: {}", source))?
+    };
+
+    let decoder = zstd::Decoder::new(bytes.as_slice())?;
+    tar::Archive::new(decoder)
+        .unpack(dest)
+        .with_context(|| format!("Failed to unpack pack: {}", source))
+}
+
+/// Verify and copy a pack directory into `packs/<name>/`
+fn install_from_dir(src: &Path, packs: &Path) -> Result<PackManifest> {
+    let manifest = load_manifest(src)?;
+    manifest.validate()?;
+
+    // Verify everything before touching the install directory
+    for melody in &manifest.melodies {
+        let file = src.join(melody.file.as_ref().unwrap());
+        let bytes = fs::read(&file).with_context(|| {
+            format!("Audio file for {} not found: {}", melody.id, file.display())
+        })?;
+
+        if Some(sha256_hex(&bytes)) != melody.sha256.as_ref().map(|s| s.to_lowercase()) {
+            anyhow::bail!("Checksum mismatch for {}: {}", melody.id, file.display());
+        }
+    }
+
+    let dest = packs.join(&manifest.name);
+    if dest.exists() {
+        fs::remove_dir_all(&dest)
+            .with_context(|| format!("Failed to replace pack: {}", dest.display()))?;
+    }
+    fs::create_dir_all(&dest)?;
+
+    fs::copy(src.join(MANIFEST_FILE), dest.join(MANIFEST_FILE))?;
+    for melody in &manifest.melodies {
+        let file = melody.file.as_ref().unwrap();
+        let target = dest.join(file);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(src.join(file), &target)
+            .with_context(|| format!("Failed to install {}", target.display()))?;
+    }
+
+    Ok(manifest)
+}
+
+fn is_valid_pack_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && !SELECTOR_PREFIXES.contains(&name)
+        && Some(name) != SYNTH_PREFIX.strip_suffix(':')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn is_safe_relative_path(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+name = "mypack"
+description = "Osaka Loop Line"
+
+[[melodies]]
+id = "JR-Osaka"
+line = "JR"
+line_name = "Osaka Loop"
+station = "Osaka"
+file = "audio/osaka.mp3"
+sha256 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+"#;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest: PackManifest = toml::from_str(MANIFEST).unwrap();
+        assert_eq!(manifest.name, "mypack");
+        assert_eq!(manifest.melodies.len(), 1);
+        assert!(manifest.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_manifest() {
+        let mut manifest: PackManifest = toml::from_str(MANIFEST).unwrap();
+        manifest.melodies[0].file = Some(PathBuf::from("../escape.mp3"));
+        assert!(manifest.validate().is_err());
+
+        let mut manifest: PackManifest = toml::from_str(MANIFEST).unwrap();
+        manifest.melodies[0].sha256 = None;
+        assert!(manifest.validate().is_err());
+
+        let mut manifest: PackManifest = toml::from_str(MANIFEST).unwrap();
+        manifest.name = "my:pack".to_string();
+        assert!(manifest.validate().is_err());
+    }
+
+    #[test]
+    fn test_pack_melodies_are_namespaced() {
+        use crate::melodies::{AudioLocation, MelodyRegistry};
+
+        let manifest: PackManifest = toml::from_str(MANIFEST).unwrap();
+        let mut registry = MelodyRegistry::new();
+        registry.add_pack(manifest, Path::new("/packs/mypack"));
+
+        let melody = registry.get("mypack:JR-Osaka").unwrap();
+        assert_eq!(
+            melody.location,
+            AudioLocation::File(Path::new("/packs/mypack/audio/osaka.mp3"))
+        );
+        assert!(registry.get("JR-Osaka").is_none());
+    }
+}
@@ -0,0 +1,179 @@
+//! Overlap and cooldown policies for hook playback.
+//!
+//! The time until which a melody is playing, and when each hook event last
+//! played, are stored in `~/.hassha/playback.json`. A melody sequence counts
+//! as a single playback. A hook checks the policies again and records its
+//! playback in one locked update, so two hooks can't both start.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::state;
+
+/// What to do when a hook fires while another melody is playing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overlap {
+    /// Play anyway, on top of the current melody
+    #[default]
+    Allow,
+    /// Skip the new melody
+    Skip,
+}
+
+/// Why a melody was not played
+#[derive(Debug, Clone, PartialEq)]
+pub enum Suppressed {
+    /// Another melody is still playing
+    Overlap,
+    /// The hook played too recently; the cooldown ends after the remaining time
+    Cooldown(Duration),
+}
+
+impl std::fmt::Display for Suppressed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Suppressed::Overlap => write!(f, "another melody is playing"),
+            Suppressed::Cooldown(remaining) => {
+                write!(f, "cooldown ({:.1}s left)", remaining.as_secs_f64())
+            }
+        }
+    }
+}
+
+/// Playback state shared by all hook invocations. Times are Unix milliseconds.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PlaybackState {
+    /// A melody is playing until this time
+    #[serde(default)]
+    pub playing_until: u64,
+
+    /// When each hook event last started a melody
+    #[serde(default)]
+    pub last_played: HashMap<String, u64>,
+}
+
+impl PlaybackState {
+    /// Check whether a melody for `event` may start at `now`
+    pub fn check(
+        &self,
+        event: &str,
+        now: u64,
+        overlap: Overlap,
+        cooldown: Option<Duration>,
+    ) -> Option<Suppressed> {
+        if overlap == Overlap::Skip && now < self.playing_until {
+            return Some(Suppressed::Overlap);
+        }
+
+        if let Some(cooldown) = cooldown
+            && let Some(&last) = self.last_played.get(event)
+        {
+            let ready = last.saturating_add(cooldown.as_millis() as u64);
+            if now < ready {
+                return Some(Suppressed::Cooldown(Duration::from_millis(ready - now)));
+            }
+        }
+
+        None
+    }
+
+    /// Record that a melody of `duration` started at `now`.
+    /// Returns the time it is expected to end.
+    pub fn start(&mut self, event: &str, now: u64, duration: Duration) -> u64 {
+        let until = now.saturating_add(duration.as_millis() as u64);
+        self.playing_until = self.playing_until.max(until);
+        self.last_played.insert(event.to_string(), now);
+        until
+    }
+
+    /// Record that a melody expected to end at `until` has finished at `now`
+    pub fn finish(&mut self, until: u64, now: u64) {
+        // Only clear our own playback, not a longer one started meanwhile
+        if self.playing_until == until {
+            self.playing_until = now.min(until);
+        }
+    }
+}
+
+/// The playback state file in `~/.hassha/`
+const STATE_FILE: &str = "playback.json";
+
+/// Load the playback state
+pub fn load_state() -> Result<PlaybackState> {
+    state::load(STATE_FILE)
+}
+
+/// Update the playback state under its lock, returning the result of `f`
+pub fn update_state<R>(f: impl FnOnce(&mut PlaybackState) -> R) -> Result<R> {
+    state::update(STATE_FILE, f)
+}
+
+/// Current time in Unix milliseconds
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlap_policy() {
+        let mut state = PlaybackState::default();
+        let until = state.start("Stop", 1_000, Duration::from_secs(5));
+        assert_eq!(until, 6_000);
+
+        assert_eq!(
+            state.check("Notification", 3_000, Overlap::Skip, None),
+            Some(Suppressed::Overlap)
+        );
+        assert_eq!(
+            state.check("Notification", 3_000, Overlap::Allow, None),
+            None
+        );
+        assert_eq!(
+            state.check("Notification", 6_000, Overlap::Skip, None),
+            None
+        );
+
+        // Finishing early frees the player
+        state.finish(until, 4_000);
+        assert_eq!(
+            state.check("Notification", 4_500, Overlap::Skip, None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cooldown_policy() {
+        let mut state = PlaybackState::default();
+        state.start("PostToolUse", 1_000, Duration::from_secs(1));
+
+        let cooldown = Some(Duration::from_secs(10));
+        assert_eq!(
+            state.check("PostToolUse", 5_000, Overlap::Allow, cooldown),
+            Some(Suppressed::Cooldown(Duration::from_secs(6)))
+        );
+        assert_eq!(state.check("Stop", 5_000, Overlap::Allow, cooldown), None);
+        assert_eq!(
+            state.check("PostToolUse", 11_000, Overlap::Allow, cooldown),
+            None
+        );
+    }
+
+    #[test]
+    fn test_finish_keeps_longer_playback() {
+        let mut state = PlaybackState::default();
+        let short = state.start("Stop", 0, Duration::from_secs(2));
+        state.start("Notification", 500, Duration::from_secs(10));
+
+        state.finish(short, 2_000);
+        assert_eq!(state.playing_until, 10_500);
+    }
+}
@@ -5,47 +5,109 @@
 
 use anyhow::{Context, Result};
 use std::path::Path;
-use std::process::Command;
+use std::process::{Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-/// Play an audio file.
+/// Whether the null audio backend is selected for this process
+static NULL_BACKEND: AtomicBool = AtomicBool::new(false);
+
+/// Use the null audio backend, which only checks that files exist
+pub fn use_null_backend() {
+    NULL_BACKEND.store(true, Ordering::SeqCst);
+}
+
+/// Whether audio goes to the null backend: selected with
+/// [`use_null_backend`] or `HASSHA_AUDIO=null`
+fn null_backend() -> bool {
+    NULL_BACKEND.load(Ordering::SeqCst)
+        || std::env::var("HASSHA_AUDIO").is_ok_and(|backend| backend == "null")
+}
+
+/// Play an audio file, stopping after `end` if given.
 ///
-/// On macOS, uses `afplay` command.
-pub fn play_audio(path: &Path, volume: f32) -> Result<()> {
+/// On macOS, uses `afplay` command. The null backend plays nothing.
+pub fn play_audio(path: &Path, volume: f32, end: Option<Duration>) -> Result<()> {
+    log::debug!(
+        path:% = path.display(),
+        volume,
+        end:? = end,
+        null = null_backend();
+        "Playing audio"
+    );
+
+    if null_backend() {
+        if !path.exists() {
+            anyhow::bail!("Audio file not found: {}", path.display());
+        }
+        return Ok(());
+    }
+
     #[cfg(target_os = "macos")]
     {
-        play_audio_macos(path, volume)
+        play_audio_macos(path, volume, end)
     }
 
     #[cfg(target_os = "linux")]
     {
-        play_audio_linux(path, volume)
+        play_audio_linux(path, volume, end)
     }
 
     #[cfg(target_os = "windows")]
     {
-        play_audio_windows(path, volume)
+        play_audio_windows(path, volume, end)
     }
 
     #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
     {
+        let _ = (path, volume, end);
         anyhow::bail!("Audio playback not supported on this platform")
     }
 }
 
+/// Run a player command to completion, killing it once `end` has elapsed.
+///
+/// A player stopped at `end` counts as a successful playback.
+fn run_player(command: &mut Command, end: Option<Duration>) -> std::io::Result<ExitStatus> {
+    let Some(end) = end else {
+        return command.status();
+    };
+
+    let mut child = command.spawn()?;
+    let started = Instant::now();
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+
+        if started.elapsed() >= end {
+            child.kill()?;
+            child.wait()?;
+            return Ok(ExitStatus::default());
+        }
+
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
 /// Play audio on macOS using afplay
 #[cfg(target_os = "macos")]
-fn play_audio_macos(path: &Path, volume: f32) -> Result<()> {
+fn play_audio_macos(path: &Path, volume: f32, end: Option<Duration>) -> Result<()> {
     // afplay volume is 0-255, where 1.0 = 100% = 255
     // But actually afplay -v takes a float where 1.0 is normal volume
     let volume_arg = volume.clamp(0.0, 1.0);
 
-    let status = Command::new("afplay")
-        .arg("-v")
-        .arg(volume_arg.to_string())
-        .arg(path)
-        .status()
-        .context("Failed to execute afplay")?;
+    let status = run_player(
+        Command::new("afplay")
+            .arg("-v")
+            .arg(volume_arg.to_string())
+            .arg(path),
+        end,
+    )
+    .context("Failed to execute afplay")?;
 
+    log::debug!(status:% = status; "afplay finished");
     if !status.success() {
         anyhow::bail!("afplay exited with status: {}", status);
     }
@@ -55,25 +117,24 @@ fn play_audio_macos(path: &Path, volume: f32) -> Result<()> {
 
 /// Play audio on Linux using paplay or aplay
 #[cfg(target_os = "linux")]
-fn play_audio_linux(path: &Path, volume: f32) -> Result<()> {
+fn play_audio_linux(path: &Path, volume: f32, end: Option<Duration>) -> Result<()> {
     // Try paplay first (PulseAudio)
-    let result = Command::new("paplay")
-        .arg("--volume")
-        .arg(((volume * 65536.0) as u32).to_string())
-        .arg(path)
-        .status();
-
-    if let Ok(status) = result {
-        if status.success() {
-            return Ok(());
-        }
+    let result = run_player(
+        Command::new("paplay")
+            .arg("--volume")
+            .arg(((volume * 65536.0) as u32).to_string())
+            .arg(path),
+        end,
+    );
+
+    match result {
+        Ok(status) if status.success() => return Ok(()),
+        Ok(status) => log::debug!(status:% = status; "paplay failed, falling back to aplay"),
+        Err(e) => log::debug!(error:% = e; "paplay unavailable, falling back to aplay"),
     }
 
     // Fall back to aplay (ALSA) - doesn't support volume directly
-    let status = Command::new("aplay")
-        .arg("-q")
-        .arg(path)
-        .status()
+    let status = run_player(Command::new("aplay").arg("-q").arg(path), end)
         .context("Failed to execute aplay (and paplay not available)")?;
 
     if !status.success() {
@@ -85,7 +146,7 @@ fn play_audio_linux(path: &Path, volume: f32) -> Result<()> {
 
 /// Play audio on Windows using PowerShell
 #[cfg(target_os = "windows")]
-fn play_audio_windows(path: &Path, _volume: f32) -> Result<()> {
+fn play_audio_windows(path: &Path, _volume: f32, end: Option<Duration>) -> Result<()> {
     // Windows Media Player COM object via PowerShell
     let path_str = path.to_string_lossy();
     let script = format!(
@@ -93,10 +154,7 @@ fn play_audio_windows(path: &Path, _volume: f32) -> Result<()> {
         path_str.replace("'", "''")
     );
 
-    let status = Command::new("powershell")
-        .arg("-c")
-        .arg(&script)
-        .status()
+    let status = run_player(Command::new("powershell").arg("-c").arg(&script), end)
         .context("Failed to execute PowerShell audio playback")?;
 
     if !status.success() {
@@ -0,0 +1,100 @@
+//! Audio duration probing for hassha.
+//!
+//! Durations come from the container metadata symphonia reads when probing
+//! a file (e.g., WAV headers, or MP3 Xing/VBRI tags and frame headers). No
+//! audio is decoded.
+
+use std::fs::File;
+use std::io::Cursor;
+use std::path::Path;
+use std::time::Duration;
+
+use symphonia::core::formats::probe::Hint;
+use symphonia::core::formats::{FormatOptions, TrackType};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::MetadataOptions;
+
+/// Probe the duration of an audio file on disk
+pub fn audio_duration(path: &Path) -> Option<Duration> {
+    let file = File::open(path).ok()?;
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+    track_duration(Box::new(file), &hint)
+}
+
+/// Probe the duration of audio in memory (MP3 or WAV)
+pub fn bytes_duration(bytes: &'static [u8]) -> Option<Duration> {
+    track_duration(Box::new(Cursor::new(bytes)), &Hint::new())
+}
+
+/// The duration of the default audio track, from its frame count
+fn track_duration(source: Box<dyn MediaSource>, hint: &Hint) -> Option<Duration> {
+    let stream = MediaSourceStream::new(source, Default::default());
+    let format = symphonia::default::get_probe()
+        .probe(
+            hint,
+            stream,
+            FormatOptions::default(),
+            MetadataOptions::default(),
+        )
+        .ok()?;
+
+    let track = format.default_track(TrackType::Audio)?;
+    let sample_rate = track.codec_params.as_ref()?.audio()?.sample_rate?;
+    let frames = track.num_frames?;
+    (sample_rate > 0).then(|| Duration::from_secs_f64(frames as f64 / sample_rate as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build an MPEG-1 Layer III, 128 kbps, 44.1 kHz stream of `frames` frames
+    fn mp3_frames(frames: usize) -> &'static [u8] {
+        let mut bytes = Vec::new();
+        for _ in 0..frames {
+            let mut frame = vec![0u8; 417];
+            frame[..4].copy_from_slice(&0xFFFB_9000u32.to_be_bytes());
+            bytes.extend(frame);
+        }
+        bytes.leak()
+    }
+
+    #[test]
+    fn test_mp3_duration() {
+        let duration = bytes_duration(mp3_frames(100)).unwrap();
+        // 100 frames * 1152 samples / 44100 Hz
+        assert_eq!(duration.as_millis(), 2612);
+    }
+
+    #[test]
+    fn test_wav_duration() {
+        // 300 ms of 8 kHz, 16-bit mono PCM silence
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend((36 + 4800u32).to_le_bytes());
+        bytes.extend(b"WAVE");
+        bytes.extend(b"fmt ");
+        bytes.extend(16u32.to_le_bytes());
+        bytes.extend(1u16.to_le_bytes()); // PCM
+        bytes.extend(1u16.to_le_bytes()); // mono
+        bytes.extend(8000u32.to_le_bytes()); // sample rate
+        bytes.extend(16000u32.to_le_bytes()); // byte rate
+        bytes.extend(2u16.to_le_bytes());
+        bytes.extend(16u16.to_le_bytes());
+        bytes.extend(b"data");
+        bytes.extend(4800u32.to_le_bytes());
+        bytes.extend([0u8; 4800]);
+
+        assert_eq!(
+            bytes_duration(bytes.leak()),
+            Some(Duration::from_millis(300))
+        );
+    }
+
+    #[test]
+    fn test_not_audio() {
+        assert!(bytes_duration(b"hello world").is_none());
+    }
+}
@@ -0,0 +1,128 @@
+//! Replaying a session's melody timeline for `hassha replay`.
+
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::cache::resolve_audio;
+use crate::config::{MelodySource, load_config};
+use crate::history::{HistoryEntry, HistoryFilter, load_history};
+use crate::melodies::MelodyRegistry;
+use crate::player::play_audio;
+
+/// Default replay speed-up
+pub const DEFAULT_SPEED: f64 = 10.0;
+
+/// Longest pause between replayed melodies, however long the real one was
+const MAX_PAUSE: Duration = Duration::from_secs(5);
+
+/// Re-play the melodies of a session (ID or unique prefix) in order, with
+/// the pauses between them shortened by `speed`
+pub fn replay_session(session: &str, speed: f64, volume: f32) -> Result<()> {
+    if !(speed > 0.0 && speed.is_finite()) {
+        anyhow::bail!("Replay speed must be a positive number, got {}", speed);
+    }
+
+    let filter = HistoryFilter {
+        session: Some(session.to_string()),
+        ..Default::default()
+    };
+    let mut entries = load_history(&filter, None)?.entries;
+    entries.reverse();
+
+    let ids: BTreeSet<&str> = entries
+        .iter()
+        .filter_map(|entry| entry.session_id.as_deref())
+        .collect();
+    match ids.len() {
+        0 => anyhow::bail!(
+            "No history for session {:?} (see `hassha history sessions`)",
+            session
+        ),
+        1 => {}
+        _ => anyhow::bail!(
+            "Session {:?} is ambiguous: {}",
+            session,
+            ids.into_iter().collect::<Vec<_>>().join(", ")
+        ),
+    }
+
+    let played: Vec<_> = entries
+        .iter()
+        .filter(|entry| entry.outcome.is_played() && !entry.melody.is_empty())
+        .collect();
+    let Some(first) = played.first() else {
+        println!("No melodies were played in this session");
+        return Ok(());
+    };
+
+    // Resolve melodies as the session's project did, including its catalogs
+    let config = load_config(Path::new(&first.project_dir)).ok().flatten();
+    let registry = MelodyRegistry::load(config.as_ref())?;
+
+    println!("Replaying {} melodies at {}x speed", played.len(), speed);
+
+    let mut previous: Option<(&HistoryEntry, Instant)> = None;
+    for &entry in &played {
+        if let Some((previous, started)) = previous {
+            let gap = (entry.timestamp - previous.timestamp)
+                .to_std()
+                .unwrap_or_default();
+            let pause = replay_pause(gap, speed);
+            std::thread::sleep(pause.saturating_sub(started.elapsed()));
+        }
+        previous = Some((entry, Instant::now()));
+
+        let offset = (entry.timestamp - first.timestamp).num_seconds();
+        println!(
+            "[+{:02}:{:02}] {} - {}",
+            offset / 60,
+            offset % 60,
+            entry.event,
+            entry.melody
+        );
+
+        // Sequences are logged as "A + B"
+        let parts: Vec<String> = entry.melody.split(" + ").map(str::to_string).collect();
+        let source = match parts.as_slice() {
+            [single] => MelodySource::Single(single.clone()),
+            _ => MelodySource::Sequence {
+                sequence: parts,
+                gap: None,
+            },
+        };
+
+        // Keep going if one melody is unavailable
+        let result = resolve_audio(&source, &registry, Default::default())
+            .and_then(|audio| play_audio(&audio.path, volume, None));
+        if let Err(e) = result {
+            eprintln!("  ✗ {:#}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// The pause before a melody recorded `gap` after the previous one. A slow
+/// speed can make `gap / speed` overflow a `Duration`, so it is computed in
+/// seconds and capped at [`MAX_PAUSE`].
+fn replay_pause(gap: Duration, speed: f64) -> Duration {
+    Duration::try_from_secs_f64(gap.as_secs_f64() / speed)
+        .unwrap_or(MAX_PAUSE)
+        .min(MAX_PAUSE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_pause() {
+        let gap = Duration::from_secs(30);
+        assert_eq!(replay_pause(gap, 10.0), Duration::from_secs(3));
+        assert_eq!(replay_pause(gap, 1.0), MAX_PAUSE);
+        assert_eq!(replay_pause(Duration::MAX, 1e-300), MAX_PAUSE);
+        assert_eq!(replay_pause(Duration::ZERO, 1e-300), Duration::ZERO);
+    }
+}
@@ -0,0 +1,413 @@
+//! Melody selectors for hassha.
+//!
+//! Selectors pick a melody from the registry each time they are played:
+//! - `tag:short` - a random melody with the tag
+//! - `random:line=JY,max_duration=6s` - a random melody matching all filters
+//! - `JY-*`, `JK-*|JB-*` - a melody whose ID matches one of the patterns
+//!
+//! Supported filters are `line`, `tag`, `station`, `min_duration` and
+//! `max_duration`. Melodies with an unknown duration (e.g., not downloaded
+//! yet) never match a duration filter.
+//!
+//! Melodies are chosen at random by default. With `selection = "sequential"`
+//! they are played in registry order, remembering the position per selector
+//! in `~/.hassha/selection.json`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::cache::is_url;
+use crate::config::parse_duration;
+use crate::melodies::{MelodyInfo, MelodyRegistry};
+use crate::state;
+
+/// Source prefixes reserved for selectors
+pub const SELECTOR_PREFIXES: &[&str] = &["tag", "random"];
+
+/// How a selector chooses among matching melodies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Selection {
+    /// A random matching melody each time
+    #[default]
+    Random,
+    /// Each matching melody in turn
+    Sequential,
+}
+
+/// A parsed melody selector
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Selector {
+    /// Melody ID patterns, where `*` matches any characters
+    pub patterns: Vec<String>,
+    pub line: Option<String>,
+    pub tags: Vec<String>,
+    pub station: Option<String>,
+    pub min_duration: Option<Duration>,
+    pub max_duration: Option<Duration>,
+}
+
+impl Selector {
+    /// Parse a selector. Returns `None` if the source is not a selector.
+    pub fn parse(source: &str) -> Option<Result<Self>> {
+        if let Some(tag) = source.strip_prefix("tag:") {
+            return Some(Ok(Self {
+                tags: vec![tag.trim().to_string()],
+                ..Default::default()
+            }));
+        }
+
+        if let Some(filters) = source.strip_prefix("random:") {
+            return Some(
+                Self::parse_filters(filters)
+                    .with_context(|| format!("Invalid selector: {}", source)),
+            );
+        }
+
+        if is_pattern(source) {
+            return Some(
+                Self::parse_patterns(source)
+                    .with_context(|| format!("Invalid selector: {}", source)),
+            );
+        }
+
+        None
+    }
+
+    fn parse_patterns(source: &str) -> Result<Self> {
+        let patterns: Vec<String> = source.split('|').map(|p| p.trim().to_string()).collect();
+
+        if let Some(pattern) = patterns
+            .iter()
+            .find(|p| p.is_empty() || p.contains(char::is_whitespace))
+        {
+            anyhow::bail!("Invalid melody ID pattern: {:?}", pattern);
+        }
+
+        Ok(Self {
+            patterns,
+            ..Default::default()
+        })
+    }
+
+    fn parse_filters(filters: &str) -> Result<Self> {
+        let mut selector = Self::default();
+
+        for filter in filters.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let (key, value) = filter
+                .split_once('=')
+                .with_context(|| format!("Expected key=value, got {:?}", filter))?;
+            let value = value.trim().to_string();
+
+            match key.trim() {
+                "line" => selector.line = Some(value),
+                "tag" => selector.tags.push(value),
+                "station" => selector.station = Some(value),
+                "min_duration" => selector.min_duration = Some(parse_duration(&value)?),
+                "max_duration" => selector.max_duration = Some(parse_duration(&value)?),
+                other => anyhow::bail!("Unknown selector filter: {}", other),
+            }
+        }
+
+        Ok(selector)
+    }
+
+    /// Check whether a melody matches all filters
+    pub fn matches(&self, melody: &MelodyInfo) -> bool {
+        self.matches_static(melody) && self.matches_duration(melody)
+    }
+
+    /// Check the filters that don't need the audio duration
+    fn matches_static(&self, melody: &MelodyInfo) -> bool {
+        (self.patterns.is_empty() || self.patterns.iter().any(|p| glob_match(p, melody.id)))
+            && self
+                .line
+                .as_ref()
+                .is_none_or(|line| melody.line.eq_ignore_ascii_case(line))
+            && self.station.as_ref().is_none_or(|station| {
+                melody.station.eq_ignore_ascii_case(station) || melody.station_jp == station
+            })
+            && self.tags.iter().all(|tag| melody.has_tag(tag))
+    }
+
+    fn matches_duration(&self, melody: &MelodyInfo) -> bool {
+        if self.min_duration.is_none() && self.max_duration.is_none() {
+            return true;
+        }
+
+        let Some(duration) = melody.duration() else {
+            return false;
+        };
+
+        self.min_duration.is_none_or(|min| duration >= min)
+            && self.max_duration.is_none_or(|max| duration <= max)
+    }
+
+    /// All registry melodies matching this selector
+    pub fn candidates(&self, registry: &MelodyRegistry) -> Vec<&'static MelodyInfo> {
+        registry
+            .all()
+            .iter()
+            .copied()
+            .filter(|m| self.matches(m))
+            .collect()
+    }
+
+    /// Melodies that may match once downloaded, ignoring duration filters
+    pub fn prefetch_candidates(&self, registry: &MelodyRegistry) -> Vec<&'static MelodyInfo> {
+        registry
+            .all()
+            .iter()
+            .copied()
+            .filter(|m| self.matches_static(m))
+            .collect()
+    }
+
+    /// Choose a random matching melody
+    pub fn choose(&self, registry: &MelodyRegistry) -> Result<&'static MelodyInfo> {
+        let candidates = self.candidates(registry);
+        if candidates.is_empty() {
+            anyhow::bail!("No melodies match the selector");
+        }
+        Ok(candidates[fastrand::usize(..candidates.len())])
+    }
+}
+
+/// Check whether a source is a melody ID pattern (e.g., `JY-*` or `JK-*|JB-*`).
+/// Only melody ID characters are allowed, so URLs and paths such as
+/// `sounds/*.mp3` are never patterns, and neither is an existing file.
+pub fn is_pattern(source: &str) -> bool {
+    (source.contains('*') || source.contains('|'))
+        && !is_url(source)
+        && source
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_:*| ".contains(c))
+        && !Path::new(source).exists()
+}
+
+/// Match a melody ID against a pattern where `*` matches any characters
+/// (case-insensitive)
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let text = text.to_lowercase();
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*` at all
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+/// Positions of sequential selectors, keyed by selector source
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SelectionState {
+    #[serde(default)]
+    pub positions: HashMap<String, usize>,
+}
+
+impl SelectionState {
+    /// Get the next index for a selector with `len` candidates, advancing its position
+    pub fn next_index(&mut self, source: &str, len: usize) -> usize {
+        let position = self.positions.entry(source.to_string()).or_insert(0);
+        let index = *position % len;
+        *position = index + 1;
+        index
+    }
+}
+
+/// The selection state file in `~/.hassha/`
+const STATE_FILE: &str = "selection.json";
+
+/// Choose the next melody in registry order for a sequential selector
+fn choose_sequential(
+    source: &str,
+    selector: &Selector,
+    registry: &MelodyRegistry,
+) -> Result<&'static MelodyInfo> {
+    let candidates = selector.candidates(registry);
+    if candidates.is_empty() {
+        anyhow::bail!("No melodies match the selector");
+    }
+
+    let index = state::update(STATE_FILE, |state: &mut SelectionState| {
+        state.next_index(source, candidates.len())
+    })?;
+
+    Ok(candidates[index])
+}
+
+/// Resolve a selector source to a concrete melody ID. Other sources are
+/// returned unchanged.
+pub fn select(source: &str, registry: &MelodyRegistry, selection: Selection) -> Result<String> {
+    let Some(selector) = Selector::parse(source) else {
+        return Ok(source.to_string());
+    };
+
+    let selector = selector?;
+    let melody = match selection {
+        Selection::Random => selector.choose(registry),
+        Selection::Sequential => choose_sequential(source, &selector, registry),
+    }
+    .with_context(|| format!("Failed to select a melody for {}", source))?;
+
+    Ok(melody.id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_selectors() {
+        assert!(Selector::parse("JY-Shibuya").is_none());
+
+        let selector = Selector::parse("tag:short").unwrap().unwrap();
+        assert_eq!(selector.tags, vec!["short"]);
+
+        let selector = Selector::parse("random:line=JY,max_duration=6s")
+            .unwrap()
+            .unwrap();
+        assert_eq!(selector.line.as_deref(), Some("JY"));
+        assert_eq!(selector.max_duration, Some(Duration::from_secs(6)));
+
+        assert!(Selector::parse("random:colour=red").unwrap().is_err());
+        assert!(Selector::parse("random:line").unwrap().is_err());
+
+        assert!(is_pattern("JY-*"));
+        assert!(is_pattern("JK-* | mypack:JB-*"));
+        assert!(!is_pattern("https://example.com/a|b.mp3"));
+        assert!(!is_pattern("sounds/*.mp3"));
+        assert!(!is_pattern("C:\\sounds\\*.wav"));
+    }
+
+    #[test]
+    fn test_select_by_line() {
+        let registry = MelodyRegistry::new();
+        let selector = Selector::parse("random:line=NEX").unwrap().unwrap();
+
+        assert_eq!(selector.candidates(&registry).len(), 4);
+        for _ in 0..10 {
+            let id = select("random:line=NEX", &registry, Selection::Random).unwrap();
+            assert!(id.starts_with("NEX-"));
+        }
+    }
+
+    #[test]
+    fn test_select_by_tag() {
+        use crate::catalog::Catalog;
+        use std::path::Path;
+
+        // Fixtures with declared durations, so nothing is probed
+        let mut registry = MelodyRegistry::new();
+        let catalog: Catalog = toml::from_str(
+            r#"
+[[melodies]]
+id = "IN-Chime"
+line = "IN"
+line_name = "In-house"
+station = "Office"
+url = "https://example.com/chime.mp3"
+duration = "2s"
+tags = ["calm"]
+
+[[melodies]]
+id = "IN-Bell"
+line = "IN"
+line_name = "In-house"
+station = "Lobby"
+url = "https://example.com/bell.mp3"
+duration = "15s"
+tags = ["upbeat"]
+"#,
+        )
+        .unwrap();
+        registry.add_catalog(catalog, Path::new("/catalogs/in.toml"));
+
+        let ids = |source: &str| -> Vec<&str> {
+            let selector = Selector::parse(source).unwrap().unwrap();
+            selector
+                .candidates(&registry)
+                .iter()
+                .map(|m| m.id)
+                .collect()
+        };
+
+        assert_eq!(ids("random:line=IN,tag=calm"), ["IN-Chime"]);
+        assert_eq!(ids("random:line=IN,tag=short"), ["IN-Chime"]);
+        assert_eq!(ids("random:line=IN,tag=long"), ["IN-Bell"]);
+        assert_eq!(ids("random:line=IN,max_duration=3s"), ["IN-Chime"]);
+        assert!(ids("random:line=IN,tag=calm,min_duration=3s").is_empty());
+
+        // Built-in melodies carry mood tags too
+        assert!(ids("tag:calm").contains(&"JY-Kanda"));
+        assert_eq!(
+            select("random:line=IN,tag=upbeat", &registry, Selection::Random).unwrap(),
+            "IN-Bell"
+        );
+    }
+
+    #[test]
+    fn test_wildcard_selectors() {
+        let registry = MelodyRegistry::new();
+
+        assert!(Selector::parse("https://example.com/a|b.mp3").is_none());
+        assert!(Selector::parse("JY-*||JB-*").unwrap().is_err());
+
+        let selector = Selector::parse("JK-*|JB-*").unwrap().unwrap();
+        let candidates = selector.candidates(&registry);
+        assert_eq!(candidates.len(), 20);
+        assert!(
+            candidates
+                .iter()
+                .all(|m| m.id.starts_with("JK-") || m.id.starts_with("JB-"))
+        );
+
+        let id = select("nex-*", &registry, Selection::Random).unwrap();
+        assert!(id.starts_with("NEX-"));
+
+        assert!(select("XX-*", &registry, Selection::Random).is_err());
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("JY-*", "JY-Tokyo"));
+        assert!(glob_match("jy-*", "JY-Tokyo"));
+        assert!(glob_match("*-Tokyo", "JK-Tokyo"));
+        assert!(glob_match("J*-S*ya", "JY-Shibuya"));
+        assert!(glob_match("JY-Tokyo", "JY-Tokyo"));
+        assert!(!glob_match("JY-*", "JK-Tokyo"));
+        assert!(!glob_match("JY-Tokyo", "JY-Tokyo-2"));
+        assert!(!glob_match("*-Tokyo*x", "JK-Tokyo"));
+    }
+
+    #[test]
+    fn test_sequential_positions() {
+        let mut state = SelectionState::default();
+        let indexes: Vec<usize> = (0..5).map(|_| state.next_index("JY-*", 3)).collect();
+        assert_eq!(indexes, vec![0, 1, 2, 0, 1]);
+
+        // Positions are tracked per selector
+        assert_eq!(state.next_index("NEX-*", 4), 0);
+
+        // A shrunk candidate list wraps around
+        assert_eq!(state.next_index("JY-*", 1), 0);
+    }
+}
@@ -0,0 +1,122 @@
+//! Small JSON state files shared by concurrent hooks.
+//!
+//! State lives in `~/.hassha/<name>.json`. An update holds an exclusive
+//! lock on `~/.hassha/<name>.lock` from reading the state to writing it
+//! back, and the file is replaced atomically, so hooks firing at the same
+//! time neither lose updates nor read a partial file. A missing or corrupt
+//! file reads as the default state.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// Get the path of a state file (`~/.hassha/<name>`)
+pub fn state_path(name: &str) -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    Ok(home.join(".hassha").join(name))
+}
+
+/// Take an advisory lock on `path`, shared or exclusive. The lock is
+/// released when the returned file is dropped.
+pub fn lock(path: &Path, exclusive: bool) -> Result<File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open lock: {}", path.display()))?;
+
+    if exclusive {
+        file.lock()?;
+    } else {
+        file.lock_shared()?;
+    }
+
+    Ok(file)
+}
+
+/// Load a state file
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> Result<T> {
+    load_at(&state_path(name)?)
+}
+
+/// Update a state file under its lock, returning the result of `f`
+pub fn update<T, R>(name: &str, f: impl FnOnce(&mut T) -> R) -> Result<R>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    update_at(&state_path(name)?, f)
+}
+
+fn load_at<T: DeserializeOwned + Default>(path: &Path) -> Result<T> {
+    match fs::read_to_string(path) {
+        // A corrupt state file only resets the state
+        Ok(content) => Ok(serde_json::from_str(&content).unwrap_or_default()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read state: {}", path.display())),
+    }
+}
+
+fn update_at<T, R>(path: &Path, f: impl FnOnce(&mut T) -> R) -> Result<R>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    let _lock = lock(&path.with_extension("lock"), true)?;
+
+    let mut state = load_at(path)?;
+    let result = f(&mut state);
+
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_string_pretty(&state)?)
+        .with_context(|| format!("Failed to write state: {}", tmp_path.display()))?;
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to write state: {}", path.display()))?;
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_concurrent_updates() {
+        let dir = std::env::temp_dir().join(format!("hassha-state-test-{}", std::process::id()));
+        let path = dir.join("counts.json");
+        let _ = fs::remove_dir_all(&dir);
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..25 {
+                        update_at(&path, |counts: &mut HashMap<String, u32>| {
+                            *counts.entry("n".to_string()).or_default() += 1;
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let counts: HashMap<String, u32> = load_at(&path).unwrap();
+        assert_eq!(counts["n"], 100);
+
+        // A corrupt file reads as the default
+        fs::write(&path, "{").unwrap();
+        let counts: HashMap<String, u32> = load_at(&path).unwrap();
+        assert!(counts.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
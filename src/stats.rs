@@ -0,0 +1,265 @@
+//! Usage statistics aggregated from the history log for `hassha stats`.
+
+use chrono::{Local, NaiveDate};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::history::HistoryEntry;
+
+/// Width of the longest bar in terminal charts
+const BAR_WIDTH: usize = 30;
+
+/// Aggregated history statistics
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Stats {
+    /// Number of hook events recorded
+    pub total: usize,
+    /// Events that played a melody
+    pub played: usize,
+    /// Events that were skipped (e.g., by a cooldown)
+    pub skipped: usize,
+    /// Events per local calendar day, oldest first
+    pub per_day: Vec<DayCount>,
+    /// Most-played melodies, most played first
+    pub melodies: Vec<Count>,
+    /// Hook fire rates per project, most active first
+    pub projects: Vec<ProjectRate>,
+    /// Tool usage from PostToolUse events, most used first
+    pub tools: Vec<Count>,
+}
+
+/// Events on one day
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DayCount {
+    pub date: NaiveDate,
+    pub count: usize,
+}
+
+/// A named count
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Count {
+    pub name: String,
+    pub count: usize,
+}
+
+/// How often hooks fire in a project
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ProjectRate {
+    pub project: String,
+    pub count: usize,
+    /// Average events per day over the active days of the history
+    pub per_day: f64,
+}
+
+impl Stats {
+    /// Aggregate history entries (in any order), keeping the `top` entries of
+    /// each ranking
+    pub fn from_entries(entries: &[HistoryEntry], top: usize) -> Self {
+        let mut per_day: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+        let mut melodies: HashMap<&str, usize> = HashMap::new();
+        let mut projects: HashMap<&str, usize> = HashMap::new();
+        let mut tools: HashMap<&str, usize> = HashMap::new();
+        let mut played = 0;
+
+        for entry in entries {
+            let date = entry.timestamp.with_timezone(&Local).date_naive();
+            *per_day.entry(date).or_default() += 1;
+            *projects.entry(&entry.project_dir).or_default() += 1;
+
+            if entry.outcome.is_played() {
+                played += 1;
+                *melodies.entry(&entry.melody).or_default() += 1;
+            }
+
+            if entry.event == "PostToolUse"
+                && let Some(tool) = &entry.tool_name
+            {
+                *tools.entry(tool).or_default() += 1;
+            }
+        }
+
+        // Rates are per active day, so a quiet week doesn't dilute them
+        let days = per_day.len().max(1) as f64;
+        let projects: Vec<ProjectRate> = ranked(projects, top)
+            .into_iter()
+            .map(|Count { name, count }| ProjectRate {
+                project: name,
+                count,
+                per_day: count as f64 / days,
+            })
+            .collect();
+
+        Self {
+            total: entries.len(),
+            played,
+            skipped: entries.len() - played,
+            per_day: per_day
+                .into_iter()
+                .map(|(date, count)| DayCount { date, count })
+                .collect(),
+            melodies: ranked(melodies, top),
+            projects,
+            tools: ranked(tools, top),
+        }
+    }
+}
+
+/// Sort counts, most first and then by name, keeping the first `top`
+fn ranked(counts: HashMap<&str, usize>, top: usize) -> Vec<Count> {
+    let mut counts: Vec<Count> = counts
+        .into_iter()
+        .map(|(name, count)| Count {
+            name: name.to_string(),
+            count,
+        })
+        .collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    counts.truncate(top);
+    counts
+}
+
+/// A bar proportional to `count`, with the longest at `max`
+fn bar(count: usize, max: usize) -> String {
+    let len = (count * BAR_WIDTH).div_ceil(max.max(1));
+    "█".repeat(len)
+}
+
+/// Format a labelled bar chart
+fn chart(output: &mut String, rows: &[(String, usize, String)]) {
+    let max = rows.iter().map(|(_, count, _)| *count).max().unwrap_or(0);
+    let label_width = rows.iter().map(|(label, _, _)| label.chars().count()).max();
+
+    for (label, count, suffix) in rows {
+        output.push_str(&format!(
+            "  {:<width$}  {} {}{}\n",
+            label,
+            bar(*count, max),
+            count,
+            suffix,
+            width = label_width.unwrap_or(0)
+        ));
+    }
+}
+
+/// Format statistics with terminal bar charts
+pub fn format_stats(stats: &Stats) -> String {
+    if stats.total == 0 {
+        return "No melody history yet.\n".to_string();
+    }
+
+    let mut output = format!(
+        "{} hook events: {} played, {} skipped\n",
+        stats.total, stats.played, stats.skipped
+    );
+
+    output.push_str("\nEvents per day:\n");
+    let days: Vec<_> = stats
+        .per_day
+        .iter()
+        .map(|day| (day.date.to_string(), day.count, String::new()))
+        .collect();
+    chart(&mut output, &days);
+
+    let sections = [
+        ("Top melodies", &stats.melodies),
+        ("Tools used", &stats.tools),
+    ];
+    for (title, counts) in sections {
+        if counts.is_empty() {
+            continue;
+        }
+        output.push_str(&format!("\n{}:\n", title));
+        let rows: Vec<_> = counts
+            .iter()
+            .map(|c| (c.name.clone(), c.count, String::new()))
+            .collect();
+        chart(&mut output, &rows);
+    }
+
+    output.push_str("\nProjects:\n");
+    let projects: Vec<_> = stats
+        .projects
+        .iter()
+        .map(|p| {
+            (
+                p.project.clone(),
+                p.count,
+                format!(" ({:.1}/day)", p.per_day),
+            )
+        })
+        .collect();
+    chart(&mut output, &projects);
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Outcome;
+    use chrono::DateTime;
+
+    fn entry(at: &str, event: &str, melody: &str, project: &str) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: DateTime::parse_from_rfc3339(at).unwrap(),
+            event: event.to_string(),
+            melody: melody.to_string(),
+            project_dir: project.to_string(),
+            session_id: None,
+            tool_name: None,
+            matcher: None,
+            outcome: Outcome::Played,
+            error: None,
+            config_path: None,
+        }
+    }
+
+    #[test]
+    fn test_stats_from_entries() {
+        let mut bash = entry("2024-01-15T12:00:00Z", "PostToolUse", "JY-Tokyo", "/a");
+        bash.tool_name = Some("Bash".to_string());
+        let mut skipped = bash.clone();
+        skipped.outcome = Outcome::Cooldown;
+        let entries = vec![
+            entry("2024-01-14T12:00:00Z", "Stop", "JY-Shibuya", "/a"),
+            entry("2024-01-15T12:00:00Z", "Stop", "JY-Shibuya", "/b"),
+            bash,
+            skipped,
+        ];
+
+        let stats = Stats::from_entries(&entries, 10);
+        assert_eq!((stats.total, stats.played, stats.skipped), (4, 3, 1));
+        assert_eq!(stats.per_day.len(), 2);
+        assert_eq!(stats.per_day[1].count, 3);
+
+        // Skipped events don't count as plays
+        assert_eq!(
+            stats.melodies,
+            vec![
+                Count {
+                    name: "JY-Shibuya".to_string(),
+                    count: 2
+                },
+                Count {
+                    name: "JY-Tokyo".to_string(),
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(stats.tools[0].count, 2);
+
+        assert_eq!(stats.projects[0].project, "/a");
+        assert_eq!(stats.projects[0].per_day, 1.5);
+
+        let top = Stats::from_entries(&entries, 1);
+        assert_eq!(top.melodies.len(), 1);
+        assert_eq!(top.projects.len(), 1);
+    }
+
+    #[test]
+    fn test_bar() {
+        assert_eq!(bar(10, 10).chars().count(), BAR_WIDTH);
+        assert_eq!(bar(1, 100).chars().count(), 1);
+        assert_eq!(bar(0, 0), "");
+    }
+}
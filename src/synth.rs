@@ -0,0 +1,105 @@
+//! Synthesized sounds for hassha.
+//!
+//! `synth:<name>` sources (e.g., `synth:door-chime`) are rendered on first use
+//! and cached as WAV files, so they work offline and need no download.
+
+use std::f32::consts::TAU;
+
+use crate::audio::{CHANNELS, Pcm, SAMPLE_RATE};
+
+/// Source prefix for synthesized sounds
+pub const SYNTH_PREFIX: &str = "synth:";
+
+/// Available synthesized sounds
+pub const SOUNDS: &[&str] = &["door-chime", "announcement-chime"];
+
+/// A bell-like note: start time and frequency, both in seconds / Hz
+struct Note {
+    start: f32,
+    frequency: f32,
+}
+
+/// Render a synthesized sound by name
+pub fn render(name: &str) -> Option<Pcm> {
+    let notes: &[Note] = match name {
+        // Two-tone door chime (E5 - C#5)
+        "door-chime" => &[
+            Note {
+                start: 0.0,
+                frequency: 659.25,
+            },
+            Note {
+                start: 0.45,
+                frequency: 554.37,
+            },
+        ],
+        // Rising chime played before station announcements (C5 - E5 - G5 - C6)
+        "announcement-chime" => &[
+            Note {
+                start: 0.0,
+                frequency: 523.25,
+            },
+            Note {
+                start: 0.25,
+                frequency: 659.25,
+            },
+            Note {
+                start: 0.5,
+                frequency: 783.99,
+            },
+            Note {
+                start: 0.75,
+                frequency: 1046.5,
+            },
+        ],
+        _ => return None,
+    };
+
+    Some(render_notes(notes, 1.2))
+}
+
+/// Mix decaying bell tones into a stereo buffer
+fn render_notes(notes: &[Note], note_length: f32) -> Pcm {
+    let end = notes.iter().map(|n| n.start).fold(0.0, f32::max) + note_length;
+    let frames = (end * SAMPLE_RATE as f32) as usize;
+    let mut samples = vec![0.0; frames * CHANNELS];
+
+    for note in notes {
+        let first = (note.start * SAMPLE_RATE as f32) as usize;
+        for frame in first..frames {
+            let t = (frame - first) as f32 / SAMPLE_RATE as f32;
+            if t > note_length {
+                break;
+            }
+
+            // A fundamental plus a quieter octave, with a short attack and
+            // exponential decay
+            let attack = (t / 0.005).min(1.0);
+            let envelope = attack * (-t * 4.0).exp();
+            let tone =
+                (TAU * note.frequency * t).sin() + 0.3 * (TAU * note.frequency * 2.0 * t).sin();
+            let value = 0.25 * envelope * tone;
+
+            samples[frame * CHANNELS] += value;
+            samples[frame * CHANNELS + 1] += value;
+        }
+    }
+
+    Pcm { samples }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_sounds() {
+        for name in SOUNDS {
+            let pcm = render(name).unwrap();
+            assert!(!pcm.samples.is_empty());
+            assert!(pcm.samples.iter().all(|s| s.abs() <= 1.0));
+        }
+
+        assert!(render("unknown").is_none());
+    }
+}
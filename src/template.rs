@@ -0,0 +1,119 @@
+//! Message templates for hook output, e.g. `"🚃 {station} departure"`.
+//!
+//! `{name}` is replaced with a variable, and `{{` / `}}` are literal braces.
+//! Unknown variables are kept as written; `hassha check` reports them.
+
+use std::collections::HashMap;
+
+/// Variables available in message templates
+pub const VARIABLES: &[&str] = &[
+    "melody",
+    "melody_name",
+    "station",
+    "station_jp",
+    "line",
+    "line_name",
+    "credit",
+    "duration",
+    "turn",
+    "event",
+    "tool",
+    "value",
+    "project",
+    "cwd",
+    "session",
+];
+
+/// A piece of a parsed template
+#[derive(Debug, PartialEq)]
+enum Piece<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+}
+
+/// Split a template into text and variables
+fn parse(template: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = template;
+
+    while let Some(i) = rest.find(['{', '}']) {
+        pieces.push(Piece::Text(&rest[..i]));
+        let brace = &rest[i..i + 1];
+        rest = &rest[i + 1..];
+
+        // Escaped brace
+        if let Some(after) = rest.strip_prefix(brace) {
+            pieces.push(Piece::Text(brace));
+            rest = after;
+            continue;
+        }
+
+        match rest.find('}') {
+            Some(end) if brace == "{" => {
+                pieces.push(Piece::Variable(rest[..end].trim()));
+                rest = &rest[end + 1..];
+            }
+            // A stray brace is kept as written
+            _ => pieces.push(Piece::Text(brace)),
+        }
+    }
+
+    pieces.push(Piece::Text(rest));
+    pieces
+}
+
+/// Render a template with the given variables. Variables without a value
+/// (e.g., `{station}` for a URL) render empty.
+pub fn render(template: &str, vars: &HashMap<&str, String>) -> String {
+    parse(template)
+        .into_iter()
+        .map(|piece| match piece {
+            Piece::Text(text) => text.to_string(),
+            Piece::Variable(name) if VARIABLES.contains(&name) => {
+                vars.get(name).cloned().unwrap_or_default()
+            }
+            Piece::Variable(name) => format!("{{{}}}", name),
+        })
+        .collect()
+}
+
+/// The unknown variables used in a template
+pub fn unknown_variables(template: &str) -> Vec<&str> {
+    parse(template)
+        .into_iter()
+        .filter_map(|piece| match piece {
+            Piece::Variable(name) if !VARIABLES.contains(&name) => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let vars = HashMap::from([
+            ("station", "Shibuya".to_string()),
+            ("tool", "Bash".to_string()),
+        ]);
+
+        assert_eq!(
+            render("🚃 {station} departure — {tool} done", &vars),
+            "🚃 Shibuya departure — Bash done"
+        );
+        assert_eq!(render("{ station }/{line}", &vars), "Shibuya/");
+        assert_eq!(render("{{station}} {bogus}", &vars), "{station} {bogus}");
+        assert_eq!(render("a } b { c", &vars), "a } b { c");
+    }
+
+    #[test]
+    fn test_unknown_variables() {
+        assert_eq!(
+            unknown_variables("{station} {statoin} {{x}} {tool}{y}"),
+            ["statoin", "y"]
+        );
+        assert!(unknown_variables("no variables").is_empty());
+    }
+}
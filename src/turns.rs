@@ -0,0 +1,98 @@
+//! Turn timing for `min_turn_duration`.
+//!
+//! `UserPromptSubmit` hooks record when each session's current turn started
+//! in `~/.hassha/turns.json`, so later events (e.g., Stop) know how long the
+//! agent has been working. Times are Unix milliseconds.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::state;
+
+/// Sessions whose last turn started longer ago than this are forgotten
+const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// When the current turn of each session started
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TurnState {
+    #[serde(default)]
+    pub started: HashMap<String, u64>,
+}
+
+impl TurnState {
+    /// Record that a turn of `session` started at `now`, forgetting
+    /// sessions that have been idle for long
+    pub fn start(&mut self, session: &str, now: u64) {
+        self.started.insert(session.to_string(), now);
+        self.started
+            .retain(|_, started| now.saturating_sub(*started) < MAX_AGE.as_millis() as u64);
+    }
+
+    /// How long the current turn of `session` has lasted at `now`, if its
+    /// start was recorded
+    pub fn elapsed(&self, session: &str, now: u64) -> Option<Duration> {
+        let started = self.started.get(session)?;
+        Some(Duration::from_millis(now.saturating_sub(*started)))
+    }
+}
+
+/// The turn state file in `~/.hassha/`
+const STATE_FILE: &str = "turns.json";
+
+/// Load the turn state
+pub fn load_turns() -> Result<TurnState> {
+    state::load(STATE_FILE)
+}
+
+/// Record that a turn of `session` started at `now`
+pub fn start_turn(session: &str, now: u64) -> Result<()> {
+    state::update(STATE_FILE, |state: &mut TurnState| {
+        state.start(session, now)
+    })
+}
+
+/// Format a turn length, e.g. "45s", "3m 20s", "5m" or "1h 5m"
+pub fn format_turn(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match (seconds / 3600, seconds % 3600 / 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, 0) => format!("{}m", m),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, 0, _) => format!("{}h", h),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_turn_state() {
+        let mut state = TurnState::default();
+        assert_eq!(state.elapsed("a", 1_000), None);
+
+        state.start("a", 1_000);
+        assert_eq!(state.elapsed("a", 61_000), Some(Duration::from_secs(60)));
+
+        // A new prompt starts a new turn
+        state.start("a", 70_000);
+        assert_eq!(state.elapsed("a", 71_000), Some(Duration::from_secs(1)));
+
+        // Idle sessions are forgotten
+        let later = 70_000 + MAX_AGE.as_millis() as u64;
+        state.start("b", later);
+        assert_eq!(state.elapsed("a", later), None);
+        assert!(state.elapsed("b", later).is_some());
+    }
+
+    #[test]
+    fn test_format_turn() {
+        assert_eq!(format_turn(Duration::from_secs(45)), "45s");
+        assert_eq!(format_turn(Duration::from_secs(200)), "3m 20s");
+        assert_eq!(format_turn(Duration::from_secs(300)), "5m");
+        assert_eq!(format_turn(Duration::from_secs(3900)), "1h 5m");
+    }
+}